@@ -0,0 +1,222 @@
+//! Benchmarks comparing `TailList` to `LinkedList`, `VecDeque` and `Vec`.
+//!
+//! Each group runs the same workload on all four collections. Run with
+//! `cargo bench`.
+
+#[macro_use]
+extern crate criterion;
+extern crate linked_tail_list;
+
+use std::collections::{LinkedList, VecDeque};
+use criterion::{black_box, BatchSize, Criterion};
+use linked_tail_list::TailList;
+
+/// The number of elements used by most benchmarks.
+const N: u64 = 1024;
+
+/// The number of elements used by the nested iteration benchmark, which is
+/// quadratic in the number of elements.
+const N_TAIL: u64 = 128;
+
+fn tail_list(n: u64) -> TailList<u64> {
+    let mut list = TailList::new();
+
+    for i in 0..n {
+        list.push(i);
+    }
+
+    list
+}
+
+fn linked_list(n: u64) -> LinkedList<u64> {
+    (0..n).collect()
+}
+
+fn vec_deque(n: u64) -> VecDeque<u64> {
+    (0..n).collect()
+}
+
+fn vec(n: u64) -> Vec<u64> {
+    (0..n).collect()
+}
+
+/// Pushing `N` elements to an empty collection.
+fn push(c: &mut Criterion) {
+    let mut group = c.benchmark_group("push");
+
+    group.bench_function("TailList", |b| b.iter(|| {
+        let mut list = TailList::new();
+        for i in 0..N { list.push(black_box(i)); }
+        list
+    }));
+    group.bench_function("LinkedList", |b| b.iter(|| {
+        let mut list = LinkedList::new();
+        for i in 0..N { list.push_front(black_box(i)); }
+        list
+    }));
+    group.bench_function("VecDeque", |b| b.iter(|| {
+        let mut list = VecDeque::new();
+        for i in 0..N { list.push_front(black_box(i)); }
+        list
+    }));
+    group.bench_function("Vec", |b| b.iter(|| {
+        let mut list = Vec::new();
+        for i in 0..N { list.push(black_box(i)); }
+        list
+    }));
+
+    group.finish();
+}
+
+/// Incrementing every element once, which for `TailList` includes the cost of
+/// moving the cursor's dummy node through the list.
+fn traverse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("traverse");
+
+    let mut list = tail_list(N);
+    group.bench_function("TailList", |b| b.iter(|| {
+        let mut cursor = list.cursor();
+        while let Some(mut val) = cursor.next() { *val += 1; }
+    }));
+
+    let mut list = linked_list(N);
+    group.bench_function("LinkedList", |b| b.iter(|| {
+        for val in list.iter_mut() { *val += 1; }
+    }));
+
+    let mut list = vec_deque(N);
+    group.bench_function("VecDeque", |b| b.iter(|| {
+        for val in list.iter_mut() { *val += 1; }
+    }));
+
+    let mut list = vec(N);
+    group.bench_function("Vec", |b| b.iter(|| {
+        for val in list.iter_mut() { *val += 1; }
+    }));
+
+    group.finish();
+}
+
+/// Visiting every pair of elements, with the first element coming before the
+/// second one. `TailList` uses a nested cursor from `TailValRef::tail`.
+fn tail(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tail");
+
+    let mut list = tail_list(N_TAIL);
+    group.bench_function("TailList", |b| b.iter(|| {
+        let mut sum = 0;
+        let mut cursor = list.cursor();
+
+        while let Some(mut next) = cursor.next() {
+            let (val, mut tail) = next.tail();
+            while let Some(other) = tail.next() { sum += **val ^ *other; }
+        }
+
+        sum
+    }));
+
+    let list = linked_list(N_TAIL);
+    group.bench_function("LinkedList", |b| b.iter(|| {
+        let mut sum = 0;
+
+        for (i, val) in list.iter().enumerate() {
+            for other in list.iter().skip(i + 1) { sum += val ^ other; }
+        }
+
+        sum
+    }));
+
+    let list = vec_deque(N_TAIL);
+    group.bench_function("VecDeque", |b| b.iter(|| {
+        let mut sum = 0;
+
+        for (i, val) in list.iter().enumerate() {
+            for other in list.range(i + 1..) { sum += val ^ other; }
+        }
+
+        sum
+    }));
+
+    let list = vec(N_TAIL);
+    group.bench_function("Vec", |b| b.iter(|| {
+        let mut sum = 0;
+
+        for (i, val) in list.iter().enumerate() {
+            for other in &list[i + 1..] { sum += val ^ other; }
+        }
+
+        sum
+    }));
+
+    group.finish();
+}
+
+/// Inserting `N` elements after the middle element of a list of `N` elements.
+/// `TailList` inserts through a `ValRef`, `LinkedList` has to split and append
+/// the list.
+fn insert_mid(c: &mut Criterion) {
+    let mut group = c.benchmark_group("insert_mid");
+    let mid = (N / 2) as usize;
+
+    group.bench_function("TailList", |b| b.iter_batched_ref(|| tail_list(N), |list| {
+        let mut cursor = list.cursor();
+        for _ in 0..mid { cursor.next(); }
+
+        let mut val_ref = cursor.next().unwrap().into_passive();
+        for i in 0..N { val_ref.insert_after(black_box(i)); }
+    }, BatchSize::SmallInput));
+
+    group.bench_function("LinkedList", |b| b.iter_batched_ref(|| linked_list(N), |list| {
+        for i in 0..N {
+            let mut tail = list.split_off(mid + 1);
+            list.push_back(black_box(i));
+            list.append(&mut tail);
+        }
+    }, BatchSize::SmallInput));
+
+    group.bench_function("VecDeque", |b| b.iter_batched_ref(|| vec_deque(N), |list| {
+        for i in 0..N { list.insert(mid + 1, black_box(i)); }
+    }, BatchSize::SmallInput));
+
+    group.bench_function("Vec", |b| b.iter_batched_ref(|| vec(N), |list| {
+        for i in 0..N { list.insert(mid + 1, black_box(i)); }
+    }, BatchSize::SmallInput));
+
+    group.finish();
+}
+
+/// Removing every odd element from a list of `N` elements.
+fn remove(c: &mut Criterion) {
+    let mut group = c.benchmark_group("remove");
+
+    group.bench_function("TailList", |b| b.iter_batched_ref(|| tail_list(N), |list| {
+        let mut cursor = list.cursor();
+
+        while let Some(val) = cursor.next() {
+            if *val % 2 == 1 { val.remove(); }
+        }
+    }, BatchSize::SmallInput));
+
+    group.bench_function("LinkedList", |b| b.iter_batched(|| linked_list(N), |mut list| {
+        let mut kept = LinkedList::new();
+
+        while let Some(val) = list.pop_front() {
+            if val % 2 == 0 { kept.push_back(val); }
+        }
+
+        kept
+    }, BatchSize::SmallInput));
+
+    group.bench_function("VecDeque", |b| b.iter_batched_ref(|| vec_deque(N), |list| {
+        list.retain(|val| val % 2 == 0);
+    }, BatchSize::SmallInput));
+
+    group.bench_function("Vec", |b| b.iter_batched_ref(|| vec(N), |list| {
+        list.retain(|val| val % 2 == 0);
+    }, BatchSize::SmallInput));
+
+    group.finish();
+}
+
+criterion_group!(benches, push, traverse, tail, insert_mid, remove);
+criterion_main!(benches);
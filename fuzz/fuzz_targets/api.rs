@@ -0,0 +1,102 @@
+//! Interprets the fuzz input as a sequence of operations on a `TailList`,
+//! including nested cursors created by `TailValRef::tail` and `into_tail`,
+//! markers, leaked cursors, detached nodes and a set of passive `ValRef`s.
+//!
+//! The operations and the `Vec` model, which mirrors all nodes including the
+//! dummy nodes of cursors and markers, are shared with the `model` test of the
+//! crate (see `src/model_tests.rs`). Every element returned by a cursor is
+//! compared to the model, and the whole list is compared to the model whenever
+//! no cursor is active.
+//!
+//! Run with `cargo +nightly fuzz run api`, which uses the address sanitizer by
+//! default. Debug assertions are enabled, so a corrupted `owning_link` panics.
+
+#![no_main]
+
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate linked_tail_list;
+
+use std::mem;
+use std::slice;
+use linked_tail_list::{Cursor, DetachedNode, Marker, TailList, ValRef};
+
+include!("../../src/model_tests.rs");
+
+/// Checks the list through its public API, so only while no cursor is active.
+struct Idle;
+
+impl Check for Idle {
+    fn new(_: &TailList<u64>) -> Idle {
+        Idle
+    }
+
+    fn check(&self, _: &Model) {}
+
+    fn check_idle(&self, list: &mut TailList<u64>, model: &Model) {
+        let vals: Vec<u64> = model.contents().into_iter().flatten().collect();
+
+        assert!(list.read_cursor().cloned().eq(vals.iter().cloned()));
+        assert_eq!(list.dummy_count(), model.items.len() - vals.len());
+    }
+}
+
+/// Decodes the fuzz input into operations. Operations which take an argument
+/// read it from the following byte, if there is any.
+fn decode(data: &[u8]) -> Vec<Op> {
+    let mut bytes = data.iter().map(|&b| b as usize);
+    let mut ops = Vec::new();
+
+    while let Some(op) = bytes.next() {
+        let mut arg = || bytes.next().unwrap_or(0);
+
+        ops.push(match op % 26 {
+            0..=4 => Op::Push,
+            5 => Op::PushNode(arg()),
+            6 | 7 => Op::Cursor,
+            8 => Op::Resume(arg()),
+            9 => Op::Purge,
+            10 => Op::DropMarker(arg()),
+            11..=16 => Op::Next(match arg() % 8 {
+                0 => OnNext::Drop,
+                1 => OnNext::InsertBefore,
+                2 => OnNext::InsertAfter,
+                3 => OnNext::Remove,
+                4 => OnNext::Detach,
+                5 => OnNext::IntoPassive,
+                6 => OnNext::Tail,
+                _ => OnNext::IntoTail,
+            }),
+            17 => Op::NextPair(match arg() % 3 {
+                0 => OnPair::Drop,
+                1 => OnPair::RemoveSecond,
+                _ => OnPair::SwapValues,
+            }),
+            18 => {
+                let arg = arg();
+                Op::NextWindow(arg, arg & 4 != 0)
+            }
+            19 => Op::InsertNode(arg()),
+            20 => Op::Coalesce(arg() as u64),
+            21 | 22 => {
+                let idx = arg();
+                Op::Passive(idx, match arg() % 5 {
+                    0 => OnPassive::InsertBefore,
+                    1 => OnPassive::InsertAfter,
+                    2 => OnPassive::Remove,
+                    3 => OnPassive::Detach,
+                    _ => OnPassive::Replace,
+                })
+            }
+            23 => Op::End,
+            24 => Op::IntoMarker,
+            _ => Op::Forget,
+        });
+    }
+
+    ops
+}
+
+fuzz_target!(|data: &[u8]| {
+    run_model::<Idle>(&decode(data));
+});
@@ -0,0 +1,50 @@
+//! This crate uses `linked-tail-list` without its `std` feature from a
+//! `no_std` crate. Building it checks that the list only requires `core` and
+//! `alloc`; its tests check that the list works in that configuration.
+
+#![no_std]
+
+extern crate alloc;
+extern crate linked_tail_list;
+
+use alloc::vec::Vec;
+use linked_tail_list::TailList;
+
+/// Builds a list containing `0..len`, removes all odd elements through a
+/// cursor and returns the remaining elements in list order.
+pub fn remove_odd(len: u32) -> Vec<u32> {
+    let mut list = TailList::new();
+
+    for i in 0..len {
+        list.push(i);
+    }
+
+    {
+        let mut cursor = list.cursor();
+
+        while let Some(val_ref) = cursor.next() {
+            if *val_ref % 2 == 1 {
+                val_ref.remove();
+            }
+        }
+    }
+
+    let mut cursor = list.cursor();
+    let mut vals = Vec::new();
+
+    while let Some(val_ref) = cursor.next() {
+        vals.push(*val_ref);
+    }
+
+    vals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::remove_odd;
+
+    #[test]
+    fn remove_odd_no_std() {
+        assert_eq!(remove_odd(8), [6, 4, 2, 0]);
+    }
+}
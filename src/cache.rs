@@ -0,0 +1,635 @@
+//! Caches with different replacement policies, built on tail lists.
+//!
+//! All caches implement the `Cache` trait:
+//!
+//! * `LruCache` evicts the least recently used entry. It is a thin wrapper
+//!   around a `LinkedHashMap`.
+//! * `LfuCache` evicts the least frequently used entry. Its entries are kept in
+//!   one list per frequency, and an entry is moved to the list of the next
+//!   frequency whenever it is used. Among the entries with the lowest
+//!   frequency, the least recently used one is evicted.
+//! * `ArcCache` implements the adaptive replacement cache (ARC) of Megiddo and
+//!   Modha. It keeps entries which have been used once and entries which have
+//!   been used more often in two lists, and the keys of entries recently
+//!   evicted from either list in two ghost lists, which adapt the size of the
+//!   former two lists to the workload.
+//! * `TtlCache` removes entries a fixed time to live after they have last been
+//!   inserted or used, and evicts the oldest entry when it is full. Its entries
+//!   are kept in a `LinkedHashMap`, ordered by the time they expire at, so
+//!   `TtlCache::sweep` only visits expired entries. The time is read from a
+//!   `Clock`, which may be replaced, e.g. in tests.
+//!
+//! Moving an entry between lists relinks its node, so it takes O(1) time and
+//! never reallocates the entry. An `LfuCache` keeps the lists of frequencies
+//! which have become empty and reuses them for new frequencies, so using an
+//! entry does not allocate a new list. All operations take O(1) (amortized)
+//! time, except for removing the last entry of the lowest frequency from an
+//! `LfuCache`, which takes time linear in the number of distinct frequencies.
+//!
+//! This module requires the `std` feature.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::mem;
+use std::time::{Duration, Instant};
+use allocator_api2::alloc::Global;
+use linked_map::{KeyRef, LinkedHashMap};
+use super::{val_ptr, NodeRef, Queue};
+
+////////////////////////////////////////////////////////////////////////////////
+// STRUCTS
+////////////////////////////////////////////////////////////////////////////////
+
+/// An entry of an `LfuCache` or `ArcCache`. The value of a ghost entry of an
+/// `ArcCache` is `None`. `meta` is the frequency of an `LfuCache` entry and
+/// the list of an `ArcCache` entry.
+struct Entry<K, V, M> {
+    key: K,
+    val: Option<V>,
+    meta: M,
+}
+
+/// The map from the keys of an `LfuCache` or `ArcCache` to the nodes of their
+/// entries.
+type EntryMap<K, V, M> = HashMap<KeyRef<K>, NodeRef<Entry<K, V, M>, Global>>;
+
+/// A cache which evicts the least recently used entry.
+pub struct LruCache<K, V> {
+    map: LinkedHashMap<K, V>,
+    capacity: usize,
+}
+
+/// A cache which evicts the least frequently used entry.
+pub struct LfuCache<K, V> {
+    map: EntryMap<K, V, u64>,
+    /// The non-empty lists of entries, by frequency.
+    buckets: HashMap<u64, Queue<Entry<K, V, u64>>>,
+    /// Lists which have become empty, for new frequencies.
+    free: Vec<Queue<Entry<K, V, u64>>>,
+    min_freq: u64,
+    capacity: usize,
+}
+
+/// The lists of an `ArcCache`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ArcList {
+    /// Entries which have been used once.
+    T1,
+    /// Entries which have been used more than once.
+    T2,
+    /// Ghost entries evicted from `T1`.
+    B1,
+    /// Ghost entries evicted from `T2`.
+    B2,
+}
+
+/// An adaptive replacement cache (see the module documentation).
+pub struct ArcCache<K, V> {
+    map: EntryMap<K, V, ArcList>,
+    lists: [Queue<Entry<K, V, ArcList>>; 4],
+    /// The number of entries in each list.
+    lens: [usize; 4],
+    /// The target length of `T1`.
+    target: usize,
+    capacity: usize,
+}
+
+/// A value of a `TtlCache` and the time it expires at, which is `None` if that
+/// time cannot be represented, i.e. if the value never expires.
+struct Timed<V> {
+    val: V,
+    expires: Option<Instant>,
+}
+
+/// A cache whose entries expire after a fixed time to live (see the module
+/// documentation).
+pub struct TtlCache<K, V, C = SystemClock> {
+    map: LinkedHashMap<K, Timed<V>>,
+    ttl: Duration,
+    capacity: usize,
+    clock: C,
+}
+
+/// A `Clock` which returns the actual time, i.e. `Instant::now()`.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SystemClock;
+
+////////////////////////////////////////////////////////////////////////////////
+// IMPLS
+////////////////////////////////////////////////////////////////////////////////
+
+impl<K: Hash + Eq, V> LruCache<K, V> {
+    /// Returns a new, empty cache holding at most `capacity` entries.
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn new(capacity: usize) -> LruCache<K, V> {
+        assert!(capacity != 0, "capacity must be non-zero");
+
+        LruCache { map: LinkedHashMap::new(), capacity }
+    }
+}
+
+impl<K: Hash + Eq, V> LfuCache<K, V> {
+    /// Returns a new, empty cache holding at most `capacity` entries.
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn new(capacity: usize) -> LfuCache<K, V> {
+        assert!(capacity != 0, "capacity must be non-zero");
+
+        LfuCache {
+            map: HashMap::new(),
+            buckets: HashMap::new(),
+            free: Vec::new(),
+            min_freq: 0,
+            capacity,
+        }
+    }
+
+    /// Returns how often the entry for `key` has been used, if there is such an
+    /// entry. Inserting an entry counts as its first use.
+    pub fn frequency(&self, key: &K) -> Option<u64> {
+        self.map.get(&KeyRef(key)).map(|node_ref| unsafe { (*val_ptr(node_ref)).meta })
+    }
+
+    /// Moves the given entry to the list of the next frequency.
+    fn touch(&mut self, node_ref: &NodeRef<Entry<K, V, u64>, Global>) {
+        let entry = unsafe { &mut *val_ptr(node_ref) };
+        let freq = entry.meta;
+        self.bucket(freq).unlink(node_ref);
+        self.remove_if_empty(freq);
+
+        entry.meta += 1;
+        self.bucket_or_insert(freq + 1).link_back(node_ref);
+    }
+
+    /// Removes the given entry, which is not part of the map anymore, and
+    /// returns its value.
+    fn take(&mut self, node_ref: NodeRef<Entry<K, V, u64>, Global>) -> Option<V> {
+        let freq = unsafe { (*val_ptr(&node_ref)).meta };
+        let entry = self.bucket(freq).remove(node_ref);
+        self.remove_if_empty(freq);
+        entry.val
+    }
+
+    /// Returns the list of entries of frequency `freq`, which must exist.
+    fn bucket(&mut self, freq: u64) -> &mut Queue<Entry<K, V, u64>> {
+        self.buckets.get_mut(&freq).expect("missing frequency")
+    }
+
+    /// Returns the list of entries of frequency `freq`, which is taken from
+    /// the free lists if it does not exist yet.
+    fn bucket_or_insert(&mut self, freq: u64) -> &mut Queue<Entry<K, V, u64>> {
+        let free = &mut self.free;
+        self.buckets.entry(freq).or_insert_with(|| free.pop().unwrap_or_else(Queue::new))
+    }
+
+    /// Moves the list of frequency `freq` to the free lists if it is empty.
+    fn remove_if_empty(&mut self, freq: u64) {
+        if self.buckets[&freq].is_empty() {
+            let queue = self.buckets.remove(&freq).expect("missing frequency");
+            self.free.push(queue);
+
+            if self.min_freq == freq {
+                self.min_freq = freq + 1;
+            }
+        }
+    }
+}
+
+impl<K: Hash + Eq, V> ArcCache<K, V> {
+    /// Returns a new, empty cache holding at most `capacity` entries, and
+    /// remembering the keys of at most `capacity` evicted entries.
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn new(capacity: usize) -> ArcCache<K, V> {
+        assert!(capacity != 0, "capacity must be non-zero");
+
+        ArcCache {
+            map: HashMap::new(),
+            lists: [Queue::new(), Queue::new(), Queue::new(), Queue::new()],
+            lens: [0; 4],
+            target: 0,
+            capacity,
+        }
+    }
+
+    /// Returns the number of entries in the given list.
+    fn len_of(&self, list: ArcList) -> usize {
+        self.lens[list as usize]
+    }
+
+    /// Moves the given entry to the back of `list`.
+    fn relink(&mut self, node_ref: &NodeRef<Entry<K, V, ArcList>, Global>, list: ArcList) {
+        let entry = unsafe { &mut *val_ptr(node_ref) };
+        self.lists[entry.meta as usize].unlink(node_ref);
+        self.lens[entry.meta as usize] -= 1;
+        entry.meta = list;
+        self.lists[list as usize].link_back(node_ref);
+        self.lens[list as usize] += 1;
+    }
+
+    /// Removes the front entry of the given list entirely.
+    fn drop_front(&mut self, list: ArcList) {
+        let node_ref = self.lists[list as usize].front().expect("empty list");
+        self.map.remove(&key_ref(&node_ref));
+        self.lists[list as usize].remove(node_ref);
+        self.lens[list as usize] -= 1;
+    }
+
+    /// Evicts an entry of `T1` or `T2` into the corresponding ghost list, which
+    /// makes room for a new entry. `in_b2` is whether the new entry's key is
+    /// in `B2`.
+    fn replace(&mut self, in_b2: bool) {
+        let (t1, t2) = (self.len_of(ArcList::T1), self.len_of(ArcList::T2));
+
+        let (from, to) = if t1 != 0
+            && (t1 > self.target || (in_b2 && t1 == self.target) || t2 == 0)
+        {
+            (ArcList::T1, ArcList::B1)
+        } else {
+            (ArcList::T2, ArcList::B2)
+        };
+
+        let node_ref = self.lists[from as usize].front().expect("empty list");
+        self.relink(&node_ref, to);
+        unsafe { (*val_ptr(&node_ref)).val = None; }
+    }
+
+    /// Returns whether the cache holds `capacity` entries.
+    fn is_full(&self) -> bool {
+        self.len_of(ArcList::T1) + self.len_of(ArcList::T2) >= self.capacity
+    }
+}
+
+impl<K: Hash + Eq, V> TtlCache<K, V> {
+    /// Returns a new, empty cache holding at most `capacity` entries, which
+    /// expire `ttl` after they have last been inserted or used.
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn new(ttl: Duration, capacity: usize) -> TtlCache<K, V> {
+        TtlCache::with_clock(ttl, capacity, SystemClock)
+    }
+}
+
+impl<K: Hash + Eq, V, C: Clock> TtlCache<K, V, C> {
+    /// Returns a new, empty cache like `new`, which reads the time from
+    /// `clock`.
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn with_clock(ttl: Duration, capacity: usize, clock: C) -> TtlCache<K, V, C> {
+        assert!(capacity != 0, "capacity must be non-zero");
+
+        TtlCache {
+            map: LinkedHashMap::new(),
+            ttl,
+            capacity,
+            clock,
+        }
+    }
+
+    /// Returns the time to live of the entries.
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Returns the clock of this cache.
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// Removes all expired entries and returns their number.
+    ///
+    /// The entries are visited with a cursor from the front, i.e. the entry
+    /// expiring first, until the first entry which has not expired yet.
+    pub fn sweep(&mut self) -> usize {
+        let now = self.clock.now();
+        let mut removed = 0;
+        let mut cursor = self.map.cursor();
+
+        while let Some(entry) = cursor.next() {
+            if !entry.get().expired(now) {
+                break;
+            }
+
+            entry.remove();
+            removed += 1;
+        }
+
+        removed
+    }
+}
+
+impl<V> Timed<V> {
+    /// Returns whether this value has expired at `now`.
+    fn expired(&self, now: Instant) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// FUNCTIONS
+////////////////////////////////////////////////////////////////////////////////
+
+/// Returns a `KeyRef` to the key of the given node.
+fn key_ref<K, V, M>(node_ref: &NodeRef<Entry<K, V, M>, Global>) -> KeyRef<K> {
+    KeyRef(unsafe { &raw const (*val_ptr(node_ref)).key })
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// TRAITS
+////////////////////////////////////////////////////////////////////////////////
+
+/// A cache holding a limited number of entries, which are evicted according to
+/// some replacement policy.
+///
+/// The trait is object safe, so the policy may be chosen at runtime.
+pub trait Cache<K, V> {
+    /// Returns the maximum number of entries.
+    fn capacity(&self) -> usize;
+
+    /// Returns the number of entries.
+    fn len(&self) -> usize;
+
+    /// Returns whether the cache holds no entries.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the value for `key`, if any, and records the use of the entry.
+    fn get(&mut self, key: &K) -> Option<&V>;
+
+    /// Returns the value for `key`, if any, without recording a use of the
+    /// entry.
+    fn peek(&self, key: &K) -> Option<&V>;
+
+    /// Inserts a value for `key`, possibly evicting another entry. If the cache
+    /// already holds an entry for the key, its value is replaced and returned,
+    /// and the use of the entry is recorded.
+    fn insert(&mut self, key: K, val: V) -> Option<V>;
+
+    /// Removes the entry for `key` and returns its value, if any.
+    fn remove(&mut self, key: &K) -> Option<V>;
+}
+
+/// A source of the current time for a `TtlCache`.
+pub trait Clock {
+    /// Returns the current time, which must never decrease.
+    fn now(&self) -> Instant;
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// TRAIT IMPLS
+////////////////////////////////////////////////////////////////////////////////
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+impl<K: Hash + Eq, V> Cache<K, V> for LruCache<K, V> {
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        if !self.map.move_to_back(key) {
+            return None;
+        }
+
+        self.map.get(key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.map.get(key)
+    }
+
+    fn insert(&mut self, key: K, val: V) -> Option<V> {
+        if let Some(old) = self.map.get_mut(&key) {
+            let old = mem::replace(old, val);
+            self.map.move_to_back(&key);
+            return Some(old);
+        }
+
+        if self.map.len() == self.capacity {
+            self.map.pop_front();
+        }
+
+        self.map.insert(key, val);
+        None
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.map.remove(key)
+    }
+}
+
+impl<K: Hash + Eq, V> Cache<K, V> for LfuCache<K, V> {
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        let node_ref = self.map.get(&KeyRef(key))?.clone();
+        self.touch(&node_ref);
+        unsafe { (*val_ptr(&node_ref)).val.as_ref() }
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        let node_ref = self.map.get(&KeyRef(key))?;
+        unsafe { (*val_ptr(node_ref)).val.as_ref() }
+    }
+
+    fn insert(&mut self, key: K, val: V) -> Option<V> {
+        if let Some(node_ref) = self.map.get(&KeyRef(&key)).cloned() {
+            self.touch(&node_ref);
+            return unsafe { (*val_ptr(&node_ref)).val.replace(val) };
+        }
+
+        // Evict the least recently used entry of the lowest frequency
+        if self.map.len() == self.capacity {
+            let min_freq = self.min_freq;
+            let node_ref = self.bucket(min_freq).front().expect("empty frequency");
+            self.map.remove(&key_ref(&node_ref));
+            self.take(node_ref);
+        }
+
+        let entry = Entry { key, val: Some(val), meta: 1 };
+        let node_ref = self.bucket_or_insert(1).push_back(entry);
+        self.map.insert(key_ref(&node_ref), node_ref);
+        self.min_freq = 1;
+        None
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        let node_ref = self.map.remove(&KeyRef(key))?;
+        let val = self.take(node_ref);
+
+        // The entry may have been the last one of the lowest frequency, while
+        // the next frequency has no entries
+        if !self.map.is_empty() && !self.buckets.contains_key(&self.min_freq) {
+            self.min_freq = *self.buckets.keys().min().expect("missing frequency");
+        }
+
+        val
+    }
+}
+
+impl<K: Hash + Eq, V> Cache<K, V> for ArcCache<K, V> {
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn len(&self) -> usize {
+        self.len_of(ArcList::T1) + self.len_of(ArcList::T2)
+    }
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        let node_ref = self.map.get(&KeyRef(key))?.clone();
+
+        // Ghost entries are misses
+        let entry = unsafe { &*val_ptr(&node_ref) };
+        entry.val.as_ref()?;
+
+        self.relink(&node_ref, ArcList::T2);
+        unsafe { (*val_ptr(&node_ref)).val.as_ref() }
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        let node_ref = self.map.get(&KeyRef(key))?;
+        unsafe { (*val_ptr(node_ref)).val.as_ref() }
+    }
+
+    fn insert(&mut self, key: K, val: V) -> Option<V> {
+        let c = self.capacity;
+
+        if let Some(node_ref) = self.map.get(&KeyRef(&key)).cloned() {
+            let list = unsafe { (*val_ptr(&node_ref)).meta };
+            let (b1, b2) = (self.len_of(ArcList::B1), self.len_of(ArcList::B2));
+
+            match list {
+                ArcList::T1 | ArcList::T2 => {}
+                // A hit in a ghost list adapts the target length of `T1`
+                ArcList::B1 => self.target = c.min(self.target + (b2 / b1).max(1)),
+                ArcList::B2 => self.target = self.target.saturating_sub((b1 / b2).max(1)),
+            }
+
+            if (list == ArcList::B1 || list == ArcList::B2) && self.is_full() {
+                self.replace(list == ArcList::B2);
+            }
+
+            self.relink(&node_ref, ArcList::T2);
+            return unsafe { (*val_ptr(&node_ref)).val.replace(val) };
+        }
+
+        let l1 = self.len_of(ArcList::T1) + self.len_of(ArcList::B1);
+        let total = l1 + self.len_of(ArcList::T2) + self.len_of(ArcList::B2);
+
+        if l1 == c {
+            if self.len_of(ArcList::T1) < c {
+                self.drop_front(ArcList::B1);
+
+                if self.is_full() {
+                    self.replace(false);
+                }
+            } else {
+                self.drop_front(ArcList::T1);
+            }
+        } else if total >= c {
+            if total == 2 * c {
+                self.drop_front(ArcList::B2);
+            }
+
+            if self.is_full() {
+                self.replace(false);
+            }
+        }
+
+        let entry = Entry { key, val: Some(val), meta: ArcList::T1 };
+        let node_ref = self.lists[ArcList::T1 as usize].push_back(entry);
+        self.lens[ArcList::T1 as usize] += 1;
+        self.map.insert(key_ref(&node_ref), node_ref);
+        None
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        let node_ref = self.map.remove(&KeyRef(key))?;
+        let list = unsafe { (*val_ptr(&node_ref)).meta };
+        self.lens[list as usize] -= 1;
+        self.lists[list as usize].remove(node_ref).val
+    }
+}
+
+/// Entries which have expired but have not been swept yet are still counted
+/// by `len`. Apart from that, they are treated as if they had been removed: they
+/// are not returned, and expired entries found by a lookup are removed.
+impl<K: Hash + Eq, V, C: Clock> Cache<K, V> for TtlCache<K, V, C> {
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        let now = self.clock.now();
+
+        {
+            let timed = self.map.get_mut(key)?;
+
+            if timed.expired(now) {
+                self.map.remove(key);
+                return None;
+            }
+
+            // Refresh the entry, which now expires last
+            timed.expires = now.checked_add(self.ttl);
+        }
+
+        self.map.move_to_back(key);
+        self.map.get(key).map(|timed| &timed.val)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        let now = self.clock.now();
+        self.map.get(key).filter(|timed| !timed.expired(now)).map(|timed| &timed.val)
+    }
+
+    fn insert(&mut self, key: K, val: V) -> Option<V> {
+        let now = self.clock.now();
+        let expires = now.checked_add(self.ttl);
+
+        if let Some(timed) = self.map.get_mut(&key) {
+            let old = mem::replace(timed, Timed { val, expires });
+            self.map.move_to_back(&key);
+            return if old.expired(now) { None } else { Some(old.val) };
+        }
+
+        // Make room by removing expired entries first, then the oldest one
+        if self.map.len() == self.capacity && self.sweep() == 0 {
+            self.map.pop_front();
+        }
+
+        self.map.insert(key, Timed { val, expires });
+        None
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        let now = self.clock.now();
+        self.map.remove(key).filter(|timed| !timed.expired(now)).map(|timed| timed.val)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    include!( "./cache_tests.rs");
+}
@@ -0,0 +1,339 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use super::{ArcCache, ArcList, Cache, Clock, LfuCache, LruCache, TtlCache};
+
+/// A clock which only advances when told to.
+#[derive(Clone)]
+struct MockClock(Rc<Cell<Instant>>);
+
+impl MockClock {
+    fn new() -> MockClock {
+        MockClock(Rc::new(Cell::new(Instant::now())))
+    }
+
+    fn advance(&self, secs: u64) {
+        self.0.set(self.0.get() + Duration::from_secs(secs));
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.0.get()
+    }
+}
+
+include!("./rng_tests.rs");
+
+/// Returns the number of hits of `cache` for a workload of `n` requests, where
+/// every miss inserts the requested key. Values are the keys times ten.
+fn hits(cache: &mut dyn Cache<u64, u64>, keys: &mut dyn FnMut() -> u64, n: usize) -> usize {
+    let mut hits = 0;
+
+    for _ in 0..n {
+        let key = keys();
+
+        match cache.get(&key) {
+            Some(&val) => {
+                assert_eq!(val, key * 10);
+                hits += 1;
+            }
+            None => {
+                assert_eq!(cache.insert(key, key * 10), None);
+            }
+        }
+
+        assert!(cache.len() <= cache.capacity());
+    }
+
+    hits
+}
+
+/// Returns all caches with the given capacity.
+fn caches(capacity: usize) -> Vec<Box<dyn Cache<u64, u64>>> {
+    vec![
+        Box::new(LruCache::new(capacity)),
+        Box::new(LfuCache::new(capacity)),
+        Box::new(ArcCache::new(capacity)),
+        Box::new(TtlCache::with_clock(Duration::from_secs(10), capacity, MockClock::new())),
+    ]
+}
+
+#[test]
+fn common_behaviour() {
+    for mut cache in caches(4) {
+        assert!(cache.is_empty());
+        assert_eq!(cache.capacity(), 4);
+
+        for i in 0..4 {
+            assert_eq!(cache.insert(i, i), None);
+        }
+
+        assert_eq!(cache.len(), 4);
+        assert_eq!(cache.insert(1, 10), Some(1));
+        assert_eq!(cache.peek(&1), Some(&10));
+        assert_eq!(cache.get(&2), Some(&2));
+        assert_eq!(cache.remove(&3), Some(3));
+        assert_eq!(cache.remove(&3), None);
+        assert_eq!(cache.get(&3), None);
+        assert_eq!(cache.len(), 3);
+
+        // Filling the cache evicts one entry at a time
+        for i in 4..8 {
+            cache.insert(i, i);
+        }
+
+        assert_eq!(cache.len(), 4);
+        assert_eq!(cache.peek(&7), Some(&7));
+    }
+}
+
+#[test]
+fn lru_evicts_least_recently_used() {
+    let mut cache = LruCache::new(3);
+
+    for i in 0..3 {
+        cache.insert(i, i);
+    }
+
+    cache.get(&0);
+    cache.insert(3, 3);
+    assert_eq!(cache.peek(&1), None);
+
+    // Peeking does not count as a use
+    cache.peek(&2);
+    cache.insert(1, 1);
+    assert_eq!(cache.peek(&2), None);
+    assert_eq!(cache.peek(&0), Some(&0));
+}
+
+#[test]
+fn lfu_evicts_least_frequently_used() {
+    let mut cache = LfuCache::new(3);
+
+    for i in 0..3 {
+        cache.insert(i, i);
+    }
+
+    cache.get(&0);
+    cache.get(&0);
+    cache.get(&2);
+    assert_eq!(cache.frequency(&0), Some(3));
+    assert_eq!(cache.frequency(&1), Some(1));
+    assert_eq!(cache.frequency(&2), Some(2));
+
+    cache.insert(3, 3);
+    assert_eq!(cache.peek(&1), None);
+    assert_eq!(cache.frequency(&3), Some(1));
+
+    // Among equally frequent entries, the least recently used one is evicted
+    cache.get(&3);
+    cache.insert(4, 4);
+    assert_eq!(cache.peek(&2), None);
+    assert_eq!(cache.peek(&3), Some(&3));
+
+    // Removing the only entry of the lowest frequency
+    assert_eq!(cache.remove(&4), Some(4));
+    cache.insert(5, 5);
+    cache.insert(6, 6);
+    assert_eq!(cache.peek(&5), None);
+    assert_eq!(cache.len(), 3);
+    assert_eq!(cache.buckets.values().map(|bucket| bucket.list.read_cursor().count()).sum::<usize>(), 3);
+
+    // Emptied lists are reused for new frequencies
+    let lists = cache.buckets.len() + cache.free.len();
+
+    for _ in 0..100 {
+        cache.get(&6);
+    }
+
+    assert_eq!(cache.frequency(&6), Some(101));
+    assert_eq!(cache.buckets.len() + cache.free.len(), lists);
+}
+
+#[test]
+fn arc_ghost_lists() {
+    let mut cache = ArcCache::new(2);
+
+    cache.insert(0, 0);
+    cache.insert(1, 1);
+    cache.get(&1);
+    assert_eq!(cache.len_of(ArcList::T1), 1);
+    assert_eq!(cache.len_of(ArcList::T2), 1);
+
+    // 0 is evicted into B1, then re-inserting it grows the target of T1
+    cache.insert(2, 2);
+    assert_eq!(cache.peek(&0), None);
+    assert_eq!(cache.get(&0), None);
+    assert_eq!(cache.len_of(ArcList::B1), 1);
+    assert_eq!(cache.target, 0);
+
+    cache.insert(0, 0);
+    assert_eq!(cache.target, 1);
+    assert_eq!(cache.peek(&0), Some(&0));
+    assert_eq!(cache.len_of(ArcList::T2), 1);
+    assert_eq!(cache.len(), 2);
+
+    // Since T1 is not longer than its target, 1 is evicted from T2
+    assert_eq!(cache.len_of(ArcList::B2), 1);
+    assert_eq!(cache.peek(&1), None);
+
+    // The directory never holds more than twice the capacity
+    let mut rng = Rng(7);
+    assert!(hits(&mut cache, &mut || rng.next() % 8, 1000) > 0);
+
+    let lists: usize = cache.lens.iter().sum();
+    assert_eq!(lists, cache.lists.iter().map(|list| list.list.read_cursor().count()).sum());
+    assert!(lists <= 4);
+    assert_eq!(lists, cache.map.len());
+}
+
+#[test]
+fn scan_resistance() {
+    let mut results = Vec::new();
+
+    // A small hot set, interleaved with a scan over many keys used once
+    for mut cache in caches(64) {
+        let mut i = 0;
+        let mut scan = 1000;
+        let mut keys = || {
+            i += 1;
+
+            if i % 2 == 0 {
+                i % 32
+            } else {
+                scan += 1;
+                scan
+            }
+        };
+
+        results.push(hits(&mut *cache, &mut keys, 10000));
+    }
+
+    // LFU and ARC keep the hot set, while LRU keeps it only partially
+    assert!(results[1] > 4900 && results[2] > 4900, "{:?}", results);
+    assert!(results[0] <= results[2], "{:?}", results);
+}
+
+#[test]
+fn random_workload() {
+    for mut cache in caches(16) {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        hits(&mut *cache, &mut || rng.next() % 64, 10000);
+
+        for key in 0..64 {
+            cache.remove(&key);
+        }
+
+        assert!(cache.is_empty());
+    }
+}
+
+#[test]
+fn drop_values() {
+    let val = Rc::new(());
+
+    {
+        let mut cache = ArcCache::new(4);
+        let mut lfu = LfuCache::new(4);
+
+        for i in 0..16 {
+            cache.insert(i, val.clone());
+            lfu.insert(i, val.clone());
+            cache.get(&(i / 2));
+            lfu.get(&(i / 2));
+        }
+
+        assert_eq!(Rc::strong_count(&val), 9);
+    }
+
+    assert_eq!(Rc::strong_count(&val), 1);
+}
+
+#[test]
+#[should_panic(expected = "capacity must be non-zero")]
+fn zero_capacity() {
+    LfuCache::<u64, u64>::new(0);
+}
+
+#[test]
+fn ttl_expires_entries() {
+    let clock = MockClock::new();
+    let mut cache = TtlCache::with_clock(Duration::from_secs(10), 8, clock.clone());
+    assert_eq!(cache.ttl(), Duration::from_secs(10));
+
+    for i in 0..4 {
+        cache.insert(i, i);
+        clock.advance(1);
+    }
+
+    // Getting an entry refreshes it, peeking does not
+    clock.advance(6);
+    assert_eq!(cache.get(&1), Some(&1));
+    assert_eq!(cache.peek(&2), Some(&2));
+    clock.advance(2);
+    assert_eq!(cache.peek(&0), None);
+    assert_eq!(cache.sweep(), 2);
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.peek(&2), None);
+
+    // Expired entries are removed by lookups
+    clock.advance(1);
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.get(&3), None);
+    assert_eq!(cache.len(), 1);
+
+    // Replacing or removing an expired entry does not return its value
+    cache.insert(4, 4);
+    clock.advance(10);
+    assert_eq!(cache.insert(4, 40), None);
+    assert_eq!(cache.remove(&1), None);
+    assert_eq!(cache.insert(4, 41), Some(40));
+    assert!(cache.map.iter().map(|(&key, _)| key).eq(vec![4]));
+    assert_eq!(cache.sweep(), 0);
+}
+
+#[test]
+fn ttl_overflow() {
+    let clock = MockClock::new();
+    let mut cache = TtlCache::with_clock(Duration::MAX, 2, clock.clone());
+
+    // Entries whose expiry time cannot be represented never expire
+    cache.insert(0, 0);
+    cache.insert(1, 1);
+    clock.advance(u32::MAX as u64);
+    assert_eq!(cache.get(&0), Some(&0));
+    assert_eq!(cache.sweep(), 0);
+    assert_eq!(cache.insert(1, 10), Some(1));
+
+    // A full cache still evicts the oldest entry
+    cache.insert(2, 2);
+    assert_eq!(cache.peek(&0), None);
+    assert_eq!(cache.remove(&1), Some(10));
+    assert_eq!(cache.len(), 1);
+}
+
+#[test]
+fn ttl_evicts_expired_first() {
+    let clock = MockClock::new();
+    let mut cache = TtlCache::with_clock(Duration::from_secs(10), 4, clock.clone());
+
+    for i in 0..4 {
+        cache.insert(i, i);
+        clock.advance(2);
+    }
+
+    // A full cache without expired entries evicts the oldest one
+    cache.get(&0);
+    cache.insert(4, 4);
+    assert_eq!(cache.peek(&1), None);
+    assert_eq!(cache.peek(&0), Some(&0));
+
+    // Otherwise, all expired entries are swept
+    clock.advance(9);
+    cache.insert(5, 5);
+    assert_eq!(cache.len(), 3);
+    assert_eq!(cache.peek(&2), None);
+    assert_eq!(cache.peek(&3), None);
+    assert_eq!(cache.peek(&0), Some(&0));
+}
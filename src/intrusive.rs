@@ -0,0 +1,359 @@
+//! An intrusive variant of the tail list.
+//!
+//! An `IntrusiveList` does not allocate its nodes. Instead, it links elements
+//! owned by the user, each of which embeds a `TailLink`. An `Adapter` maps
+//! between an element and its embedded link, and is usually implemented with
+//! the `tail_link_adapter!` macro.
+//!
+//! A `TailLink` is a bare node header, so links use the same scheme, and the
+//! same functions, as the nodes of a `TailList`: aside from the link to the
+//! next element, each `TailLink` references the link which links to it (its
+//! owning link). This allows an element to be removed from its list in O(1) by
+//! calling `TailLink::unlink`.
+//!
+//! Elements are borrowed for the `'elem` lifetime of the list and only ever
+//! accessed through shared references, so they can neither be moved nor
+//! dropped while the list exists. Use `Cell` or similar types for element
+//! fields which should be modified while the element is linked.
+//!
+//! The borrow does not protect the elements of a list which has been leaked,
+//! e.g. by `mem::forget`. Elements are therefore pushed pinned: `TailLink` is
+//! `!Unpin`, and the memory of a pinned element must not be reused before it
+//! is dropped. Dropping its `TailLink` unlinks the element, so a list never
+//! refers to freed elements.
+
+use alloc::boxed::Box;
+use core::marker::{PhantomData, PhantomPinned};
+use core::pin::Pin;
+use core::ptr;
+use super::{clear_owning_link, detach, link_at, move_after, next_real_node, Header, Link, Own,
+            OwnRef, Ref, TAG_CURSOR, TAG_NODE};
+
+#[doc(hidden)]
+pub use core::mem::offset_of as __offset_of;
+
+////////////////////////////////////////////////////////////////////////////////
+// STRUCTS
+////////////////////////////////////////////////////////////////////////////////
+
+/// The contents of a `TailLink`: the header of a node without a value or an
+/// allocator. Iff the address of `owning_link` is null, the link is not part
+/// of any list. Unlike in a `TailList`, links do not own the linked to node.
+type Node = Header<(), ()>;
+
+/// A Link which actually owns it's contents.
+type LinkOwn = Own<Link<(), ()>>;
+
+/// A reference to a Link.
+type LinkRef = Ref<Link<(), ()>>;
+
+/// A reference to a Node.
+type NodeRef = Ref<Node>;
+
+/// A `TailLink` must be embedded in every element of an `IntrusiveList`.
+///
+/// An element can be part of at most one list per embedded `TailLink`. If a
+/// linked `TailLink` is dropped, it is unlinked first.
+#[repr(transparent)]
+pub struct TailLink(Own<Node>, PhantomPinned);
+
+/// An intrusive tail list (see the module documentation).
+///
+/// Like the head of a `TailList`, the head link is allocated separately and
+/// referenced by a `Ref`, so moving the list does not invalidate it.
+pub struct IntrusiveList<'elem, A: Adapter + 'elem> {
+    head: LinkRef,
+    phantom: PhantomData<&'elem A::Elem>,
+}
+
+/// An `IntrusiveCursor` iterates over the elements of an `IntrusiveList`. Like
+/// a `Cursor`, it is an active item, which owns the next element it would
+/// return, and keeps its place using a dummy link.
+///
+/// Since elements are only ever accessed through shared references, which do
+/// not borrow the cursor, `IntrusiveCursor` implements `Iterator`.
+pub struct IntrusiveCursor<'node, 'elem: 'node, A: Adapter + 'elem> {
+    /// A `TailLink` allocated by the cursor.
+    dummy: NodeRef,
+    phantom: PhantomData<&'node mut IntrusiveList<'elem, A>>,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// IMPLS
+////////////////////////////////////////////////////////////////////////////////
+
+impl TailLink {
+    /// Creates a new, unlinked `TailLink`.
+    pub fn new() -> TailLink {
+        TailLink::with_tag(TAG_NODE)
+    }
+
+    /// Creates a new, unlinked `TailLink` with the given tag (see `Header`).
+    fn with_tag(tag: usize) -> TailLink {
+        TailLink(Own::new(Header {
+            next: Own::new(Link::new()),
+            owning_link: ptr::without_provenance_mut(tag),
+            alloc: (),
+        }), PhantomPinned)
+    }
+
+    /// Returns `true` iff this link is part of a list.
+    pub fn is_linked(&self) -> bool {
+        !self.0.borrow_inner().is_orphan()
+    }
+
+    /// Removes this link, and thereby the element it is embedded in, from the
+    /// list it is part of. Returns `false` if the link was not linked.
+    pub fn unlink(&self) -> bool {
+        if !self.is_linked() {
+            return false;
+        }
+
+        unlink(&self.0.new_ref());
+        true
+    }
+}
+
+impl<'elem, A: Adapter + 'elem> IntrusiveList<'elem, A> {
+    /// Creates a new empty list.
+    ///
+    /// This allocates the head of the list, like `TailList::new_in`.
+    pub fn new() -> IntrusiveList<'elem, A> {
+        let head: Box<LinkOwn> = Box::new(Own::new(Link::new()));
+
+        IntrusiveList {
+            head: Ref::new(Box::into_raw(head) as *mut Link<(), ()>),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns `true` iff this list contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.head.borrow_inner().opt_node_ref().is_none()
+    }
+
+    /// Pushes an element to the front of the list. The element is pinned, so
+    /// it is unlinked before its memory is reused, even if the list is leaked
+    /// (see the module documentation).
+    ///
+    /// Panics if the element's link is already part of a list.
+    pub fn push(&mut self, elem: Pin<&'elem A::Elem>) {
+        let node = unsafe { node_ref::<A>(elem.get_ref()) };
+
+        assert!(node.borrow_inner().is_orphan(), "element is already linked");
+
+        link_at(&self.head, &node);
+    }
+
+    /// Returns a cursor over all elements in this list.
+    pub fn cursor<'node>(&'node mut self) -> IntrusiveCursor<'node, 'elem, A> {
+        IntrusiveCursor::new(&self.head)
+    }
+
+    /// Removes and frees the dummy links of leaked cursors and returns their
+    /// number, like `TailList::purge_dummies`. They are freed when the list is
+    /// dropped as well.
+    pub fn purge_dummies(&mut self) -> usize {
+        let mut purged = 0;
+        let mut link = self.head.new_ref();
+
+        while let Some(node_ref) = link.borrow_inner().opt_node_ref() {
+            match node_ref.borrow_inner().tag() {
+                TAG_CURSOR => {
+                    unsafe { free_dummy(node_ref) };
+                    purged += 1;
+                }
+                _ => link = node_ref.borrow_inner().next.new_ref(),
+            }
+        }
+
+        purged
+    }
+}
+
+impl<'node, 'elem: 'node, A: Adapter + 'elem> IntrusiveCursor<'node, 'elem, A> {
+    /// Returns a new cursor with it's dummy link inserted after the given
+    /// link.
+    fn new(at: &LinkRef) -> IntrusiveCursor<'node, 'elem, A> {
+        let dummy = Box::new(TailLink::with_tag(TAG_CURSOR));
+        let dummy = Ref::new(Box::into_raw(dummy) as *mut Node);
+        link_at(at, &dummy);
+
+        IntrusiveCursor {
+            dummy,
+            phantom: PhantomData,
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// FUNCTIONS
+////////////////////////////////////////////////////////////////////////////////
+
+/// Returns a `NodeRef` to the link embedded in `elem`.
+///
+/// The reference is derived from `elem` itself, so it may be used to access
+/// the whole element again.
+unsafe fn node_ref<A: Adapter>(elem: &A::Elem) -> NodeRef {
+    Ref::new(A::link(elem) as *mut Node)
+}
+
+/// Unlinks the given (linked) node from the list.
+fn unlink(node_ref: &NodeRef) {
+    detach(node_ref);
+    clear_owning_link(node_ref);
+}
+
+/// Frees the given dummy link, which must have been allocated by an
+/// `IntrusiveCursor` that no longer uses it.
+unsafe fn free_dummy(node_ref: NodeRef) {
+    // Dropping the `TailLink` unlinks it
+    drop(Box::from_raw(node_ref.get_mut_ptr() as *mut TailLink));
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// TRAITS
+////////////////////////////////////////////////////////////////////////////////
+
+/// An `Adapter` maps between the elements of an `IntrusiveList` and the
+/// `TailLink`s embedded in them. Prefer implementing it with the
+/// `tail_link_adapter!` macro.
+///
+/// # Safety
+///
+/// `link` must return a pointer to a `TailLink` embedded in the element, which
+/// is derived from `elem`, and `elem` must be the exact inverse of `link`.
+pub unsafe trait Adapter {
+    /// The type of the elements.
+    type Elem;
+
+    /// Returns a pointer to the link embedded in the element `elem` points to.
+    ///
+    /// # Safety
+    ///
+    /// `elem` must point to a valid element.
+    unsafe fn link(elem: *const Self::Elem) -> *const TailLink;
+
+    /// Returns a pointer to the element in which the link `link` points to is
+    /// embedded.
+    ///
+    /// # Safety
+    ///
+    /// `link` must have been returned by `link`.
+    unsafe fn elem(link: *const TailLink) -> *const Self::Elem;
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// TRAIT IMPLS
+////////////////////////////////////////////////////////////////////////////////
+
+impl Default for TailLink {
+    fn default() -> TailLink {
+        TailLink::new()
+    }
+}
+
+impl Drop for TailLink {
+    fn drop(&mut self) {
+        self.unlink();
+    }
+}
+
+impl<'elem, A: Adapter + 'elem> Default for IntrusiveList<'elem, A> {
+    fn default() -> IntrusiveList<'elem, A> {
+        IntrusiveList::new()
+    }
+}
+
+impl<'elem, A: Adapter + 'elem> Drop for IntrusiveList<'elem, A> {
+    fn drop(&mut self) {
+        // No cursor is alive anymore, so the dummy links left in the list
+        // belong to leaked cursors
+        while let Some(node_ref) = self.head.borrow_inner().opt_node_ref() {
+            match node_ref.borrow_inner().tag() {
+                TAG_CURSOR => unsafe { free_dummy(node_ref) },
+                _ => unlink(&node_ref),
+            }
+        }
+
+        unsafe { drop(Box::from_raw(self.head.get_mut_ptr() as *mut LinkOwn)); }
+    }
+}
+
+impl<'node, 'elem: 'node, A: Adapter + 'elem> Iterator
+    for IntrusiveCursor<'node, 'elem, A>
+{
+    type Item = &'elem A::Elem;
+
+    fn next(&mut self) -> Option<&'elem A::Elem> {
+        let dummy = self.dummy.clone();
+
+        // Find the next node, skipping the dummy links of other cursors
+        let next_ref = next_real_node(&dummy.borrow_inner().next)?;
+
+        // Move the dummy link behind the next node
+        move_after(&dummy, &next_ref);
+
+        // Return the element the next node is embedded in
+        unsafe { Some(&*A::elem(next_ref.get_mut_ptr() as *const TailLink)) }
+    }
+}
+
+impl<'node, 'elem: 'node, A: Adapter + 'elem> Drop
+    for IntrusiveCursor<'node, 'elem, A>
+{
+    fn drop(&mut self) {
+        unsafe { free_dummy(self.dummy.clone()) };
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// MACROS
+////////////////////////////////////////////////////////////////////////////////
+
+/// Declares a unit struct `$adapter` implementing `Adapter` for the elements
+/// of type `$elem`, whose `TailLink` is stored in the field `$field`.
+///
+/// ```
+/// #[macro_use] extern crate linked_tail_list;
+///
+/// use linked_tail_list::intrusive::{IntrusiveList, TailLink};
+///
+/// struct Timer { deadline: u64, link: TailLink }
+///
+/// tail_link_adapter!(TimerAdapter = Timer { link });
+///
+/// fn main() {
+///     let timer = std::pin::pin!(Timer { deadline: 10, link: TailLink::new() });
+///     let mut list = IntrusiveList::<TimerAdapter>::new();
+///     list.push(timer.as_ref());
+///
+///     assert_eq!(list.cursor().next().map(|t| t.deadline), Some(10));
+/// }
+/// ```
+#[macro_export]
+macro_rules! tail_link_adapter {
+    ($vis:vis $adapter:ident = $elem:ty { $field:ident }) => {
+        $vis struct $adapter;
+
+        unsafe impl $crate::intrusive::Adapter for $adapter {
+            type Elem = $elem;
+
+            unsafe fn link(elem: *const $elem)
+                           -> *const $crate::intrusive::TailLink {
+                &raw const (*elem).$field
+            }
+
+            unsafe fn elem(link: *const $crate::intrusive::TailLink)
+                           -> *const $elem {
+                let offset = $crate::intrusive::__offset_of!($elem, $field);
+                (link as *const u8).sub(offset) as *const $elem
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    include!( "./intrusive_tests.rs");
+}
@@ -0,0 +1,216 @@
+use std::cell::Cell;
+use std::pin::{pin, Pin};
+use super::{IntrusiveList, LinkOwn, LinkRef, TailLink, OwnRef};
+
+struct Elem {
+    val: Cell<u64>,
+    link: TailLink,
+}
+
+tail_link_adapter!(ElemAdapter = Elem { link });
+
+impl Elem {
+    fn new(val: u64) -> Elem {
+        Elem { val: Cell::new(val), link: TailLink::new() }
+    }
+
+    /// Returns `n` pinned elements with the values `0..n`.
+    fn pinned(n: u64) -> Vec<Pin<Box<Elem>>> {
+        (0..n).map(|val| Box::pin(Elem::new(val))).collect()
+    }
+}
+
+/// Validate the list, `line` is used in the error message.
+fn validate(list: &IntrusiveList<ElemAdapter>, line: u32) {
+    let mut this_link = list.head.new_ref();
+    let mut next_ref_opt = this_link.borrow_inner().opt_node_ref();
+
+    while let Some(next_ref) = next_ref_opt {
+        let ol_ptr = next_ref.borrow_inner().owning_link().get_mut_ptr();
+
+        assert!(ol_ptr == this_link.get_mut_ptr(), "invalid list ptr at line {}", line);
+
+        this_link = next_ref.borrow_inner().next.new_ref();
+        next_ref_opt = this_link.borrow_inner().opt_node_ref();
+    }
+}
+
+/// Leaks `list` like `mem::forget`, but returns its head, so the test can free
+/// it with `free_head` once no element links to it anymore.
+fn forget(list: IntrusiveList<ElemAdapter>) -> LinkRef {
+    let head = list.head.new_ref();
+    std::mem::forget(list);
+    head
+}
+
+/// Frees the head of a list leaked by `forget`.
+fn free_head(head: LinkRef) {
+    assert!(head.borrow_inner().opt_node_ref().is_none(), "head is still linked to");
+    unsafe { drop(Box::from_raw(head.get_mut_ptr() as *mut LinkOwn)); }
+}
+
+/// Collects the values of all elements of the list, in order.
+fn vals(list: &mut IntrusiveList<ElemAdapter>) -> Vec<u64> {
+    list.cursor().map(|elem| elem.val.get()).collect()
+}
+
+#[test]
+fn push_and_iter() {
+    let elems = Elem::pinned(256);
+    let mut list = IntrusiveList::new();
+
+    for elem in &elems {
+        list.push(elem.as_ref());
+        validate(&list, line!());
+    }
+
+    {
+        let mut cursor = list.cursor();
+
+        for i in 0u64..256 {
+            let elem = cursor.next().unwrap();
+            assert_eq!(elem.val.get(), 255 - i);
+            elem.val.set(elem.val.get() * 2);
+        }
+
+        assert!(cursor.next().is_none());
+    }
+
+    validate(&list, line!());
+    assert_eq!(vals(&mut list), (0u64..256).rev().map(|i| i * 2).collect::<Vec<_>>());
+    assert!(elems.iter().all(|e| e.link.is_linked()));
+
+    drop(list);
+    assert!(elems.iter().all(|e| !e.link.is_linked()));
+}
+
+#[test]
+fn unlink_self() {
+    let elems = Elem::pinned(64);
+    let mut list = IntrusiveList::new();
+
+    for elem in &elems {
+        list.push(elem.as_ref());
+    }
+
+    for elem in elems.iter().filter(|e| e.val.get() % 2 == 1) {
+        assert!(elem.link.unlink());
+        assert!(!elem.link.unlink());
+        validate(&list, line!());
+    }
+
+    assert_eq!(vals(&mut list), (0u64..32).rev().map(|i| i * 2).collect::<Vec<_>>());
+
+    {
+        for elem in list.cursor() {
+            elem.link.unlink();
+        }
+    }
+
+    assert!(list.is_empty());
+    validate(&list, line!());
+
+    list.push(elems[1].as_ref());
+    assert_eq!(vals(&mut list), [1]);
+}
+
+#[test]
+#[should_panic(expected = "element is already linked")]
+fn push_linked() {
+    let elem = pin!(Elem::new(0));
+    let mut a = IntrusiveList::<ElemAdapter>::new();
+    let mut b = IntrusiveList::<ElemAdapter>::new();
+
+    a.push(elem.as_ref());
+    b.push(elem.as_ref());
+}
+
+#[test]
+fn drop_linked_elem() {
+    let a = pin!(Elem::new(1));
+    let mut list = IntrusiveList::<ElemAdapter>::new();
+
+    let head = {
+        let b = pin!(Elem::new(2));
+
+        let mut tmp = IntrusiveList::<ElemAdapter>::new();
+        tmp.push(b.as_ref());
+        tmp.push(a.as_ref());
+        forget(tmp)
+    };
+
+    assert!(a.link.unlink());
+    list.push(a.as_ref());
+    assert_eq!(vals(&mut list), [1]);
+    free_head(head);
+}
+
+#[test]
+fn skip_many_dummies() {
+    let elems = Elem::pinned(2);
+    let mut list = IntrusiveList::<ElemAdapter>::new();
+
+    list.push(elems[1].as_ref());
+    list.push(elems[0].as_ref());
+
+    // Enough dummy nodes to overflow the stack if they were skipped recursively
+    let m = if cfg!(miri) { 64 } else { 100_000 };
+
+    for _ in 0..m {
+        std::mem::forget(list.cursor());
+    }
+
+    assert_eq!(vals(&mut list), [0, 1]);
+    validate(&list, line!());
+
+    // The dummy links of the leaked cursors are freed again
+    assert_eq!(list.purge_dummies(), m);
+    validate(&list, line!());
+    assert_eq!(list.purge_dummies(), 0);
+    assert_eq!(vals(&mut list), [0, 1]);
+}
+
+#[test]
+fn drop_elems_of_forgotten_list() {
+    let mut elems = Elem::pinned(64);
+    let mut list = IntrusiveList::<ElemAdapter>::new();
+
+    for elem in &elems {
+        list.push(elem.as_ref());
+    }
+
+    let head = forget(list);
+
+    // Pinned elements are dropped in place, which unlinks them from the leaked
+    // list before their memory is freed
+    elems.reserve(1 << 20);
+    elems.retain(|elem| elem.val.get() % 3 != 0);
+    assert!(elems.iter().all(|elem| elem.link.is_linked()));
+    drop(elems);
+    free_head(head);
+}
+
+#[test]
+fn cursors_skip_each_other() {
+    let elems = Elem::pinned(4);
+    let mut list = IntrusiveList::<ElemAdapter>::new();
+
+    for elem in elems.iter().rev() {
+        list.push(elem.as_ref());
+    }
+
+    // A leaked cursor in the middle of the list is skipped by other cursors
+    {
+        let mut cursor = list.cursor();
+        cursor.next();
+        cursor.next();
+        std::mem::forget(cursor);
+    }
+
+    let mut cursor = list.cursor();
+    assert_eq!(cursor.by_ref().map(|elem| elem.val.get()).collect::<Vec<_>>(), [0, 1, 2, 3]);
+    assert!(cursor.next().is_none());
+    drop(cursor);
+
+    validate(&list, line!());
+}
@@ -14,51 +14,204 @@
 //! predecessors nor successors.
 //!
 //! A `TailList`, `Cursor` and `TailValRef` are active items. A `ValRef` is a
-//! passive item.
+//! passive item. A `Marker` is no item at all: it only keeps the position of a
+//! cursor in the list. Neither is a `ReadCursor`, which only borrows the list
+//! immutably, so any number of them may coexist.
 //!
 //! An active item may temporarily transfer ownership of it's owned node to
 //! another item by creating a mutable borrow to itself.
-
-use std::cell::UnsafeCell;
-use std::marker::PhantomData;
-use std::mem;
-use std::ops::{Deref, DerefMut};
+//!
+//! The crate is `no_std` (but requires `alloc`) if the default `std` feature
+//! is disabled. Without `std`, the `linked_map` and `cache` modules are not
+//! available. On targets without atomic pointer-sized integers, `Handle`s,
+//! `Snapshot`s and the `timer` module are not available either, since they
+//! need ids which are unique among all lists (like `alloc::sync`).
+//!
+//! The value of an element is never moved while its node is allocated, i.e.
+//! between pushing it and removing it from a list, even if its node is
+//! detached and inserted into another list. Only methods which return or
+//! replace a value move it, while values are dropped in place by the list.
+//! This allows pinning elements: a `pinned::PinnedList` only hands out its
+//! elements pinned, while `TailList::push_pinned` and `ValRef::as_pin_mut` pin
+//! the elements of a `TailList` if the caller promises not to move them.
+//!
+//! All nodes of a list, including the dummy nodes of its cursors, are
+//! allocated from the list's allocator (see `TailList::new_in`), except nodes
+//! allocated by another list and inserted as a `DetachedNode`. Allocators are
+//! provided by the `allocator-api2` crate. So is the table of the elements
+//! which have a `Handle`.
+//!
+//! Since a detached node may be freed by a different list than the one which
+//! allocated it, every node stores a clone of its allocator. This costs
+//! nothing for zero-sized allocators like `Global`, but `size_of::<A>()` bytes
+//! per node otherwise, e.g. a pointer for a reference to an arena.
+//!
+//! Creating a list is not free: the link to its first node (the head) is
+//! allocated from the list's allocator as well, so the owning link of the
+//! first node stays put when the `TailList` is moved. `TailList::new`,
+//! `TailList::new_in` and `Default` therefore allocate once, and so does every
+//! type built on tail lists when it creates its lists, e.g. a `TimerWheel`
+//! when a timer is linked into one of its slots for the first time. Keep
+//! empty lists around rather than creating them in hot paths.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+#[cfg(any(feature = "std", test))]
+extern crate core;
+extern crate alloc;
+extern crate allocator_api2;
+#[cfg(test)]
+#[macro_use]
+extern crate proptest;
+
+#[cfg(target_has_atomic = "ptr")]
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use allocator_api2::alloc::{handle_alloc_error, Allocator, Global, Layout};
+use allocator_api2::boxed::Box;
+use core::array;
+use core::cell::UnsafeCell;
+#[cfg(target_has_atomic = "ptr")]
+use core::cmp;
+#[cfg(target_has_atomic = "ptr")]
+use core::convert::TryFrom;
+use core::marker::PhantomData;
+use core::fmt;
+use core::mem;
+use core::ops::{Deref, DerefMut};
+use core::pin::Pin;
+use core::ptr::{self, NonNull};
+#[cfg(target_has_atomic = "ptr")]
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature = "std")]
+pub mod cache;
+pub mod intrusive;
+#[cfg(feature = "std")]
+pub mod linked_map;
+pub mod pinned;
+#[cfg(target_has_atomic = "ptr")]
+pub mod timer;
+pub mod wait_queue;
 
 ////////////////////////////////////////////////////////////////////////////////
 // STRUCTS
 ////////////////////////////////////////////////////////////////////////////////
 
 /// A struct actually owning its contents.
+#[repr(transparent)]
 struct Own<T>(UnsafeCell<T>); // TODO?: NonZero
 
 /// A struct only referencing its contents.
-struct Ref<T>(*mut T); // TODO: NonZero
+///
+/// The pointer is never null, so `Option<Ref<T>>` is no larger than `Ref<T>`.
+struct Ref<T>(NonNull<T>);
 
-/// An actual Link to another Node.
-struct Link<T>(Option<Box<NodeOwn<T>>>);
+/// An actual Link to another Node, which owns the linked to node.
+///
+/// The node is referenced by a raw pointer, so moving a `Link` never
+/// invalidates other references to the node. Links are aligned to at least
+/// four bytes, even on targets with smaller pointers, so the lowest two bits
+/// of their address are free for the tag of `Header::owning_link`.
+#[repr(align(4))]
+struct Link<T, A>(Option<NodeRef<T, A>>);
 
 /// A Link which actually owns it's contents.
-type LinkOwn<T> = Own<Link<T>>;
+type LinkOwn<T, A> = Own<Link<T, A>>;
 
 /// A reference to a Link.
-type LinkRef<T> = Ref<Link<T>>;
+type LinkRef<T, A> = Ref<Link<T, A>>;
+
+/// The header of every Node. A dummy Node consists of only a header, while the
+/// header of a real Node is the first field of a `Node`. The node was allocated
+/// from `alloc`.
+///
+/// The lowest two bits of `owning_link` are a tag (see `TAG_NODE` etc.). They
+/// are always zero in the address of an actual link, so nodes do not have to
+/// store a separate tag.
+struct Header<T, A> {
+    next: LinkOwn<T, A>,
+    owning_link: *mut Link<T, A>,
+    alloc: A,
+}
 
-/// An actual Node. Iff `val` is `None`, this is a dummy Node.
-struct Node<T> {
-    next: LinkOwn<T>,
-    owning_link: LinkRef<T>,
-    val: Option<T>,
+/// The tag of a real Node.
+const TAG_NODE: usize = 0;
+
+/// The tag of the dummy Node of a `Cursor`.
+const TAG_CURSOR: usize = 1;
+
+/// The tag of the dummy Node of a `Marker`. If the list has been dropped, the
+/// address of `owning_link` is null.
+const TAG_MARKER: usize = 2;
+
+/// The bits of `owning_link` used by the tag.
+const TAG_MASK: usize = 3;
+
+// The tag must fit into the bits which are zero in the address of every link
+const _: () = assert!(mem::align_of::<Link<(), ()>>() > TAG_MASK);
+
+/// A Header which actually owns it's contents, i.e. a dummy Node.
+type HeaderOwn<T, A> = Own<Header<T, A>>;
+
+/// An actual (non-dummy) Node. `slot` is the index of the element's slot in
+/// the table of handles of its list (see `Shared`), or `NO_SLOT`.
+#[repr(C)]
+struct Node<T, A> {
+    header: Header<T, A>,
+    val: T,
+    slot: u32,
 }
 
+/// The `slot` of a node which has no slot.
+const NO_SLOT: u32 = u32::MAX;
+
 /// A Node which actually owns it's contents.
-type NodeOwn<T> = Own<Node<T>>;
+type NodeOwn<T, A> = Own<Node<T, A>>;
+
+/// A reference to the header of a Node, which may be a dummy Node.
+type NodeRef<T, A> = Ref<Header<T, A>>;
+
+/// The state of a list which its cursors and `ValRef`s need besides the nodes:
+/// its allocator and the table of slots used by `Handle`s. They reference the
+/// state of their list, which does not move while they borrow it.
+///
+/// An element is given a slot when the first handle to it is created. The slot
+/// becomes vacant when the element is removed from the list and is reused for
+/// the next element which needs one, so the table only grows to the largest
+/// number of elements which have had handles at the same time.
+struct Shared<T, A: Allocator + Clone> {
+    /// The allocator all nodes of the list are allocated from.
+    alloc: A,
+    /// The id of the list (see `unique_id`), or zero if it has not been
+    /// assigned one yet.
+    list: usize,
+    /// The id the next element which needs one gets.
+    next: u64,
+    /// The slots, indexed by the `slot` of the nodes.
+    slots: allocator_api2::vec::Vec<Slot<T, A>, A>,
+    /// The index of the first vacant slot, or `NO_SLOT`.
+    vacant: u32,
+}
 
-/// A reference to a Node.
-type NodeRef<T> = Ref<Node<T>>;
+/// A slot in the table of handles of a list.
+enum Slot<T, A> {
+    /// The slot of the element at `node`, whose id is `id`.
+    Element { node: NodeRef<T, A>, id: u64 },
+    /// A vacant slot, followed by the vacant slot at the given index, or by
+    /// none if it is `NO_SLOT`.
+    Vacant(u32),
+}
 
 /// A specialized linked list (see the module documentation).
-pub struct TailList<T> {
-    head: LinkOwn<T>,
+///
+/// The head link is allocated separately, so it does not move with the list
+/// and the `owning_link` of the first node stays valid. It is referenced by a
+/// `Ref` rather than a `Box`, since moving a `Box` would invalidate that
+/// pointer.
+pub struct TailList<T, A: Allocator + Clone = Global> {
+    head: LinkRef<T, A>,
+    shared: Shared<T, A>,
 }
 
 // Lifetimes:
@@ -67,29 +220,134 @@ pub struct TailList<T> {
 // 'node: The lifetime of any single node, usually the lifetime of the list
 // 'tail: The lifetime of the tail (only used for TailValRef)
 // 'slf:  Used in function calls to explicitly denote the self lifetime
+// 'elem: The lifetime of the elements of an intrusive list
 
 /// A `Cursor` is an iterator over a node and it's tail. It is an active item,
 /// which owns the next node it would return.
 ///
 /// Due to the design of rust's `Iterator` trait, `Cursor` cannot implement
 /// `Iterator`.
-pub struct Cursor<'node, T: 'node> {
-    dummy: NodeRef<T>,
-    phantom: PhantomData<&'node mut Node<T>>,
+///
+/// A cursor places a dummy node into the list, which is removed when the
+/// cursor is dropped. If the cursor is leaked instead (e.g. by `mem::forget`),
+/// its dummy node stays in the list and is skipped by all other cursors, until
+/// it is removed by `TailList::purge_dummies` or the list is dropped.
+pub struct Cursor<'node, T: 'node, A: 'node + Allocator + Clone = Global> {
+    dummy: NodeRef<T, A>,
+    shared: Ref<Shared<T, A>>,
+    phantom: PhantomData<&'node mut Node<T, A>>,
+}
+
+/// A `ReadCursor` is an iterator over the elements of a list, which borrows the
+/// list immutably.
+///
+/// Unlike a `Cursor`, it does not insert a dummy node into the list, so any
+/// number of read cursors may exist at the same time, and they can be cloned.
+pub struct ReadCursor<'node, T: 'node, A: 'node + Allocator + Clone = Global> {
+    /// Never a dummy node, since dropping a marker frees its dummy node while
+    /// the list is borrowed.
+    next: Option<NodeRef<T, A>>,
+    phantom: PhantomData<&'node TailList<T, A>>,
 }
 
 /// A `ValRef` is a passive item, which provides mutable access to a single
 /// node.
-pub struct ValRef<'node, T: 'node> {
-    node: NodeRef<T>,
-    phantom: PhantomData<&'node Node<T>>,
+pub struct ValRef<'node, T: 'node, A: 'node + Allocator + Clone = Global> {
+    node: NodeRef<T, A>,
+    shared: Ref<Shared<T, A>>,
+    phantom: PhantomData<&'node Node<T, A>>,
+}
+
+/// A `MappedValRef` provides mutable access to a part of the element of a
+/// `ValRef`, see `ValRef::map`.
+pub struct MappedValRef<'node, U: 'node> {
+    val: &'node mut U,
 }
 
 /// A `TailValRef` is an active item, which provides mutable access to a single
 /// node and its successors.
-pub struct TailValRef<'node, 'tail, T: 'node + 'tail> {
-    val_ref: ValRef<'node, T>,
-    phantom: PhantomData<Cursor<'tail, T>>,
+pub struct TailValRef<'node, 'tail, T: 'node + 'tail,
+                      A: 'node + 'tail + Allocator + Clone = Global> {
+    val_ref: ValRef<'node, T, A>,
+    phantom: PhantomData<Cursor<'tail, T, A>>,
+}
+
+/// A `Marker` keeps the position of a `Cursor` in the list, without borrowing
+/// the list (see `Cursor::into_marker` and `TailList::resume`).
+///
+/// The marker owns the dummy node of the cursor, which other cursors skip. If
+/// the marker is dropped, its dummy node is removed from the list.
+pub struct Marker<T, A: Allocator + Clone = Global> {
+    dummy: NodeRef<T, A>,
+}
+
+/// A `DetachedNode` is an element which has been detached from its list by
+/// `ValRef::detach`, but still owns the allocation of its node.
+///
+/// It can be inserted into a list again without allocating, e.g. with
+/// `TailList::push_node`, or be turned back into its value.
+pub struct DetachedNode<T, A: Allocator + Clone = Global> {
+    node: NodeRef<T, A>,
+}
+
+/// A `Handle` identifies a single element of a list, without borrowing it.
+///
+/// A handle is resolved by `TailList::try_get` in constant time, which fails if
+/// the element has been removed from the list in the meantime, even if its
+/// slot or its memory has been reused for a new element: besides the index of
+/// the element's slot in a table of the list, a handle stores the ids of the
+/// list and of the element, which are never given to another list or another
+/// element of the same list.
+///
+/// An element is given a slot and an id when the first handle to it is
+/// created. The table of slots is allocated from the list's allocator, and
+/// every node stores the index of its slot, which takes four bytes unless they
+/// fit into the padding after the value.
+#[cfg(target_has_atomic = "ptr")]
+pub struct Handle<T, A = Global> {
+    slot: u32,
+    list: usize,
+    id: u64,
+    phantom: PhantomData<*const Header<T, A>>,
+}
+
+/// The errors returned by the fallible methods of this crate.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Error {
+    /// The targeted node is the dummy node of a cursor or marker, which has no
+    /// value. A `ValRef` only references one if its list has been corrupted,
+    /// e.g. by unsafe code.
+    Dummy,
+    /// The element referenced by a `Handle` is no longer part of the list.
+    Stale,
+    /// The allocator failed to allocate a new node.
+    Alloc,
+    /// The entries of a `Snapshot` contain the same id more than once.
+    DuplicateId,
+}
+
+/// A `Snapshot` is a copy of the elements of a list, in order, where each
+/// element is paired with an id (see `TailList::snapshot`).
+///
+/// The entries may be persisted in any format and later be restored into a
+/// new list, giving back a `Handle` for every id. An element which has a
+/// handle keeps its id while it is part of the list, and every element keeps
+/// its saved id in the restored list, so saved ids identify the same elements
+/// after restoring.
+#[cfg(target_has_atomic = "ptr")]
+pub struct Snapshot<T> {
+    entries: Vec<(u64, T)>,
+}
+
+/// A list of elements, ordered from the least to the most recently inserted
+/// one, whose nodes are referenced by its user. Used by the `wait_queue`,
+/// `linked_map` and `cache` modules.
+///
+/// Elements are inserted directly before the dummy node of `tail`, which is
+/// always the last node of `list`.
+struct Queue<T> {
+    list: TailList<T>,
+    tail: Marker<T>,
 }
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -101,56 +359,433 @@ impl<T> Own<T> {
     fn new(val: T) -> Own<T> { Own(UnsafeCell::new(val)) }
 }
 
-impl<T> Link<T> {
-    /// Returns a new `Link` linking to nothing.
-    fn new() -> Link<T> { Link(None) }
-
-    /// Returns as new `Link` linking to the given node.
-    fn new_to_node(node: NodeOwn<T>) -> Link<T> {
-        Link(Some(Box::new(node)))
+impl<T> Ref<T> {
+    /// Returns a new `Ref` referencing the value at `ptr`, which must not be
+    /// null.
+    fn new(ptr: *mut T) -> Ref<T> {
+        debug_assert!(!ptr.is_null(), "null Ref");
+        unsafe { Ref(NonNull::new_unchecked(ptr)) }
     }
+}
+
+impl<T, A> Link<T, A> {
+    /// Returns a new `Link` linking to nothing.
+    fn new() -> Link<T, A> { Link(None) }
 
     /// Returns an optional `NodeRef` to the linked to node, any.
-    fn opt_node_ref(&self) -> Option<NodeRef<T>> {
-        self.0.as_ref().map(|owned| owned.new_ref())
+    fn opt_node_ref(&self) -> Option<NodeRef<T, A>> {
+        self.0.clone()
     }
 }
 
-impl<T> Node<T> {
-    /// Returns a new node with the given `val` and `owning_link`.
-    fn new(val: Option<T>, owning_link: LinkRef<T>) -> Node<T> {
-        Node {
+impl<T, A> Header<T, A> {
+    /// Returns a new header with the given `owning_link`, `tag` and `alloc`.
+    fn new(owning_link: LinkRef<T, A>, tag: usize, alloc: A) -> Header<T, A> {
+        Header {
             next: Own::new(Link::new()),
-            owning_link: owning_link,
-            val: val,
+            owning_link: owning_link.get_mut_ptr().map_addr(|addr| addr | tag),
+            alloc,
+        }
+    }
+
+    /// Returns a reference to the link owning this node.
+    fn owning_link(&self) -> LinkRef<T, A> {
+        Ref::new(self.owning_link.map_addr(|addr| addr & !TAG_MASK))
+    }
+
+    /// Returns the tag of this node.
+    fn tag(&self) -> usize {
+        self.owning_link.addr() & TAG_MASK
+    }
+
+    /// Returns whether this is the header of a dummy node.
+    fn is_dummy(&self) -> bool {
+        self.tag() != TAG_NODE
+    }
+
+    /// Sets the `owning_link` of the given node to `link`, which may be null,
+    /// tagged with `tag`.
+    fn set_owning_link(node_ref: &NodeRef<T, A>, link: *mut Link<T, A>, tag: usize) {
+        // Write the field through the raw pointer, without borrowing the node
+        unsafe {
+            (*node_ref.get_mut_ptr()).owning_link = link.map_addr(|addr| addr | tag);
         }
     }
+
+    /// Returns whether the address of `owning_link` is null, i.e. whether this
+    /// is the dummy node of a `Marker` whose list has been dropped, or an
+    /// unlinked `TailLink`.
+    fn is_orphan(&self) -> bool {
+        self.owning_link.addr() & !TAG_MASK == 0
+    }
 }
 
 impl<T> TailList<T> {
-    /// Creates a new empty list.
+    /// Creates a new empty list using the global allocator.
+    ///
+    /// This allocates the head of the list, see `new_in`.
     pub fn new() -> TailList<T> {
+        TailList::new_in(Global)
+    }
+}
+
+impl<T, A: Allocator + Clone> TailList<T, A> {
+    /// Creates a new empty list, whose nodes are allocated from `alloc`.
+    ///
+    /// The head of the list, i.e. the link to its first node, is allocated
+    /// from `alloc` right away, so the list can be moved without touching its
+    /// nodes. It is freed when the list is dropped.
+    pub fn new_in(alloc: A) -> TailList<T, A> {
+        let head = Box::new_in(Own::new(Link::<T, A>::new()), alloc.clone());
+
         TailList {
-            head: Own::new(Link::new()),
+            head: Ref::new(Box::into_raw_with_allocator(head).0 as *mut Link<T, A>),
+            shared: Shared {
+                slots: allocator_api2::vec::Vec::new_in(alloc.clone()),
+                alloc,
+                list: 0,
+                next: 1,
+                vacant: NO_SLOT,
+            },
+        }
+    }
+
+    /// Returns a reference to the allocator of this list.
+    pub fn allocator(&self) -> &A {
+        &self.shared.alloc
+    }
+
+    /// Returns a `ValRef` to the element identified by `handle`, or
+    /// `Error::Stale` if that element is no longer part of this list.
+    ///
+    /// The element is looked up in the table of slots of this list, so this
+    /// takes constant time.
+    #[cfg(target_has_atomic = "ptr")]
+    pub fn try_get<'node>(&'node mut self, handle: Handle<T, A>)
+                          -> Result<ValRef<'node, T, A>, Error> {
+        if handle.list != self.shared.list {
+            return Err(Error::Stale);
+        }
+
+        // The slot may have been reused by another element, but that has a
+        // different id
+        let node_ref = match self.shared.slots.get(handle.slot as usize) {
+            Some(&Slot::Element { ref node, id }) if id == handle.id => node.clone(),
+            _ => return Err(Error::Stale),
+        };
+
+        Ok(ValRef::new(node_ref, self.shared_ref()))
+    }
+
+    /// Removes the element identified by `handle` from this list and returns
+    /// its value, or returns `Error::Stale` if that element is no longer part
+    /// of this list.
+    #[cfg(target_has_atomic = "ptr")]
+    pub fn try_remove(&mut self, handle: Handle<T, A>) -> Result<T, Error> {
+        self.try_get(handle)?.try_remove()
+    }
+
+    /// Inserts a new element before the element identified by `handle` and
+    /// returns a `ValRef` to it, or returns `Error::Stale` if that element is
+    /// no longer part of this list, or `Error::Alloc` if the node cannot be
+    /// allocated.
+    #[cfg(target_has_atomic = "ptr")]
+    pub fn try_insert_before<'node>(&'node mut self, handle: Handle<T, A>, val: T)
+                                    -> Result<ValRef<'node, T, A>, Error> {
+        self.try_get(handle)?.try_insert_before(val)
+    }
+
+    /// Inserts a new element after the element identified by `handle` and
+    /// returns a `ValRef` to it, or returns `Error::Stale` if that element is
+    /// no longer part of this list, or `Error::Alloc` if the node cannot be
+    /// allocated.
+    #[cfg(target_has_atomic = "ptr")]
+    pub fn try_insert_after<'node>(&'node mut self, handle: Handle<T, A>, val: T)
+                                   -> Result<ValRef<'node, T, A>, Error> {
+        self.try_get(handle)?.try_insert_after(val)
+    }
+
+    /// Returns a cursor continuing at the position of the cursor which has been
+    /// turned into `marker`.
+    ///
+    /// The list is searched for the marker, so this takes time linear in the
+    /// position of the marker.
+    ///
+    /// Panics if `marker` belongs to a different list.
+    pub fn resume<'node>(&'node mut self, marker: Marker<T, A>) -> Cursor<'node, T, A> {
+        let dummy = self.find(marker.dummy.get_mut_ptr())
+            .expect("marker does not belong to this list");
+
+        // The dummy node is owned by the cursor again
+        mem::forget(marker);
+        set_tag(&dummy, TAG_CURSOR);
+
+        Cursor {
+            dummy,
+            shared: self.shared_ref(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns a reference to the shared state of this list, for a cursor or
+    /// `ValRef` borrowing the list.
+    fn shared_ref(&mut self) -> Ref<Shared<T, A>> {
+        Ref::new(&mut self.shared)
+    }
+
+    /// Returns a reference to the node at `node` (which may be dangling), if
+    /// it is part of this list.
+    fn find(&mut self, node: *const Header<T, A>) -> Option<NodeRef<T, A>> {
+        let mut next_ref_opt = self.head.borrow_inner().opt_node_ref();
+
+        while let Some(node_ref) = next_ref_opt {
+            if ptr::eq(node_ref.get_mut_ptr(), node) {
+                return Some(node_ref);
+            }
+
+            next_ref_opt = node_ref.borrow_inner().next.borrow_inner()
+                .opt_node_ref();
         }
+
+        None
     }
 
     /// Pushed a new element to the front of the list.
     pub fn push(&mut self, val: T) {
-        insert_at(&self.head, Some(val));
+        insert_at(&self.head, Some(val), self.shared.alloc.clone());
+    }
+
+    /// Pushes a new element to the front of the list and returns it pinned.
+    ///
+    /// # Safety
+    ///
+    /// The element must not be moved until it is dropped, see
+    /// `ValRef::as_pin_mut`.
+    pub unsafe fn push_pinned(&mut self, val: T) -> Pin<&mut T> {
+        self.push(val);
+
+        let node_ref = self.head.borrow_inner().opt_node_ref()
+            .expect("pushed element is missing");
+        Pin::new_unchecked(&mut *val_ptr(&node_ref))
+    }
+
+    /// Pushes a detached node to the front of the list. The node keeps the
+    /// allocator it has been allocated from.
+    pub fn push_node(&mut self, node: DetachedNode<T, A>) {
+        link_at(&self.head, &node.into_ref());
     }
 
     /// Returns a cursor over all elements in this list.
-    pub fn cursor<'node>(&'node mut self) -> Cursor<'node, T> {
-        Cursor::new(&self.head.new_ref())
+    pub fn cursor<'node>(&'node mut self) -> Cursor<'node, T, A> {
+        let shared = self.shared_ref();
+        Cursor::new(&self.head.new_ref(), shared)
+    }
+
+    /// Merges adjacent elements of this list, see `Cursor::coalesce`.
+    pub fn coalesce<F>(&mut self, merge: F) where F: FnMut(&mut T, &T) -> bool {
+        self.cursor().coalesce(merge)
+    }
+
+    /// Removes the dummy nodes of leaked cursors from this list and returns
+    /// their number.
+    ///
+    /// No cursor can be alive while the list is borrowed mutably, so all dummy
+    /// nodes except those of markers are left behind by leaked cursors.
+    pub fn purge_dummies(&mut self) -> usize {
+        let mut purged = 0;
+        let mut link = self.head.new_ref();
+
+        while let Some(node_ref) = link.borrow_inner().opt_node_ref() {
+            match node_ref.borrow_inner().tag() {
+                TAG_CURSOR => {
+                    discard(node_ref);
+                    purged += 1;
+                }
+                _ => link = node_ref.borrow_inner().next.new_ref(),
+            }
+        }
+
+        purged
+    }
+
+    /// Returns the number of dummy nodes in this list, which includes those of
+    /// markers and leaked cursors.
+    pub fn dummy_count(&self) -> usize {
+        let mut count = 0;
+        let mut next_ref_opt = self.head.borrow_inner().opt_node_ref();
+
+        while let Some(node_ref) = next_ref_opt {
+            if node_ref.borrow_inner().is_dummy() {
+                count += 1;
+            }
+
+            next_ref_opt = node_ref.borrow_inner().next.borrow_inner()
+                .opt_node_ref();
+        }
+
+        count
+    }
+
+    /// Returns a read-only cursor over all elements in this list.
+    pub fn read_cursor<'node>(&'node self) -> ReadCursor<'node, T, A> {
+        ReadCursor {
+            next: next_real_node(&self.head),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns a `Snapshot` of all elements in this list.
+    ///
+    /// Elements which have a handle are saved with their id (see
+    /// `Handle::id`). The other elements are saved with new ids, which are
+    /// not kept by the list, so they do not take up slots: such an element
+    /// gets a different id in the next snapshot, unless a handle to it is
+    /// created in the meantime. All elements of a restored list have handles.
+    #[cfg(target_has_atomic = "ptr")]
+    pub fn snapshot(&mut self) -> Snapshot<T> where T: Clone {
+        let mut entries = Vec::new();
+        let mut next_ref_opt = next_real_node(&self.head);
+
+        while let Some(node_ref) = next_ref_opt {
+            let id = match unsafe { *slot_ptr(&node_ref) } {
+                NO_SLOT => next_id(&mut self.shared),
+                slot => slot_id(&self.shared, slot),
+            };
+
+            entries.push((id, unsafe { (*val_ptr(&node_ref)).clone() }));
+            next_ref_opt = next_real_node(&node_ref.borrow_inner().next);
+        }
+
+        Snapshot { entries }
+    }
+}
+
+#[cfg(target_has_atomic = "ptr")]
+impl<T, A> Handle<T, A> {
+    /// Returns the id of the element of this handle. The element keeps its id
+    /// while it is part of its list, and it has that id in a `Snapshot` of
+    /// the list and in the list restored from the snapshot.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+#[cfg(target_has_atomic = "ptr")]
+impl<T> Snapshot<T> {
+    /// Creates a new snapshot from the given entries, usually ones previously
+    /// obtained from `into_entries`. Returns `Error::DuplicateId` if an id
+    /// occurs more than once.
+    pub fn from_entries(entries: Vec<(u64, T)>) -> Result<Snapshot<T>, Error> {
+        let mut ids: Vec<u64> = entries.iter().map(|&(id, _)| id).collect();
+        ids.sort_unstable();
+
+        if ids.windows(2).any(|ids| ids[0] == ids[1]) {
+            return Err(Error::DuplicateId);
+        }
+
+        Ok(Snapshot { entries })
+    }
+
+    /// Returns the entries of this snapshot, in list order.
+    pub fn entries(&self) -> &[(u64, T)] {
+        &self.entries
+    }
+
+    /// Consumes this snapshot and returns its entries, in list order.
+    pub fn into_entries(self) -> Vec<(u64, T)> {
+        self.entries
+    }
+
+    /// Rebuilds the list this snapshot was taken from, and returns it together
+    /// with a map from each id to a `Handle` to the element with that id.
+    pub fn restore(self) -> (TailList<T>, BTreeMap<u64, Handle<T>>) {
+        self.restore_in(Global)
+    }
+
+    /// Like `restore`, but the nodes of the list are allocated from `alloc`.
+    pub fn restore_in<A>(self, alloc: A) -> (TailList<T, A>, BTreeMap<u64, Handle<T, A>>)
+        where A: Allocator + Clone
+    {
+        let mut list = TailList::new_in(alloc);
+        let mut handles = BTreeMap::new();
+
+        for (id, val) in self.entries.into_iter().rev() {
+            let node = insert_at(&list.head, Some(val), list.shared.alloc.clone());
+
+            // Give the element its saved id instead of a new one. Ids of new
+            // elements follow the largest saved id.
+            occupy_slot(&mut list.shared, &node, id);
+            list.shared.next = cmp::max(list.shared.next, id.saturating_add(1));
+            handles.insert(id, ValRef::new(node, list.shared_ref()).handle());
+        }
+
+        (list, handles)
     }
 }
 
-impl<'node, T: 'node> Cursor<'node, T> {
+impl<T> Queue<T> {
+    /// Returns a new, empty queue.
+    fn new() -> Queue<T> {
+        let mut list = TailList::new();
+        let tail = list.cursor().into_marker();
+
+        Queue { list, tail }
+    }
+
+    /// Returns whether this queue contains no elements.
+    #[cfg(feature = "std")]
+    fn is_empty(&self) -> bool {
+        self.front().is_none()
+    }
+
+    /// Returns a reference to the least recently inserted node, if any.
+    fn front(&self) -> Option<NodeRef<T, Global>> {
+        // Only the dummy node of `tail` is skipped
+        next_real_node(&self.list.head)
+    }
+
+    /// Allocates a node for `val`, inserts it at the back and returns a
+    /// reference to it.
+    fn push_back(&mut self, val: T) -> NodeRef<T, Global> {
+        insert_at(&self.tail.dummy.borrow_inner().owning_link(), Some(val), Global)
+    }
+
+    /// Inserts the given unlinked node at the back.
+    #[cfg(feature = "std")]
+    fn link_back(&mut self, node_ref: &NodeRef<T, Global>) {
+        link_at(&self.tail.dummy.borrow_inner().owning_link(), node_ref);
+    }
+
+    /// Inserts the given unlinked node at the front.
+    #[cfg(feature = "std")]
+    fn link_front(&mut self, node_ref: &NodeRef<T, Global>) {
+        link_at(&self.list.head, node_ref);
+    }
+
+    /// Unlinks the given node, which must be part of this queue, without
+    /// freeing it.
+    fn unlink(&mut self, node_ref: &NodeRef<T, Global>) {
+        detach(node_ref);
+    }
+
+    /// Removes the given node, which must be part of this queue, frees it and
+    /// returns its value.
+    fn remove(&mut self, node_ref: NodeRef<T, Global>) -> T {
+        detach(&node_ref);
+        unsafe { free_node(node_ref) }
+    }
+}
+
+impl<'node, T: 'node, A: 'node + Allocator + Clone> Cursor<'node, T, A> {
     /// Returns a new cursor with it's dummy node inserted after the given link.
-    fn new(at: &LinkRef<T>) -> Cursor<'node, T> {
+    /// `shared` is the shared state of the list, whose allocator the dummy node
+    /// is allocated from.
+    fn new(at: &LinkRef<T, A>, shared: Ref<Shared<T, A>>) -> Cursor<'node, T, A> {
+        let alloc = shared.borrow_inner().alloc.clone();
+
         Cursor {
-            dummy: insert_at(at, None),
+            dummy: insert_at(at, None, alloc),
+            shared,
             phantom: PhantomData,
         }
     }
@@ -159,78 +794,331 @@ impl<'node, T: 'node> Cursor<'node, T> {
     ///
     /// This cursor is unusable as long as the `'tail` lifetime is still
     /// referenced.
-    pub fn next<'tail>(&'tail mut self) -> Option<TailValRef<'node, 'tail, T>> {
-        // Get a reference to the next node, if there is any
-        let next_ref_opt: Option<NodeRef<T>> = self.dummy.borrow_inner().next
+    pub fn next<'tail>(&'tail mut self) -> Option<TailValRef<'node, 'tail, T, A>> {
+        // Find the next non-dummy node, if there is any, skipping the dummy
+        // nodes of other cursors and markers
+        let mut last_dummy = None;
+        let mut next_ref_opt: Option<NodeRef<T, A>> = self.dummy.borrow_inner().next
             .borrow_inner().opt_node_ref();
 
+        while let Some(next_ref) = next_ref_opt.take() {
+            match next_ref.borrow_inner().tag() {
+                TAG_NODE => {
+                    next_ref_opt = Some(next_ref);
+                    break;
+                }
+                _ => {
+                    next_ref_opt = next_ref.borrow_inner().next.borrow_inner()
+                        .opt_node_ref();
+                    last_dummy = Some(next_ref);
+                }
+            }
+        }
+
         let next_ref = match next_ref_opt {
             Some(next_ref) => next_ref,
-            None => return None,
+            None => {
+                // Move the dummy node behind the skipped dummy nodes
+                if let Some(last_dummy) = last_dummy {
+                    move_after(&self.dummy, &last_dummy);
+                }
+
+                return None;
+            }
         };
 
-        // Swap the places of the dummy and next nodes in the list
-        swap_places(&self.dummy, &next_ref);
-
-        // If the next node happens to be a dummy node, skip it by calling next
-        // again
-        if next_ref.borrow_inner().val.is_none() {
-            return self.next();
-        }
+        // Move the dummy node behind the next node
+        move_after(&self.dummy, &next_ref);
 
         // Return the next node
         Some(TailValRef {
-            val_ref: ValRef {
-                node: next_ref,
-                phantom: PhantomData,
-            },
+            val_ref: ValRef::new(next_ref, self.shared.clone()),
             phantom: PhantomData,
         })
     }
+
+    /// (Optionally) returns the next element of this cursor and the element
+    /// following it.
+    ///
+    /// Only the first element is consumed: it is the second element of the
+    /// pair returned by the next call. If there are fewer than two elements
+    /// left, returns `None` without consuming anything.
+    ///
+    /// Both elements may be modified and removed, e.g. to merge the second
+    /// element into the first one.
+    pub fn next_pair<'tail>(&'tail mut self)
+                            -> Option<(ValRef<'tail, T, A>, ValRef<'tail, T, A>)> {
+        // Only advance if there is a pair
+        let first = next_real_node(&self.dummy.borrow_inner().next)?;
+        next_real_node(&first.borrow_inner().next)?;
+
+        let first = self.next()?.val_ref.node;
+        let second = next_real_node(&self.dummy.borrow_inner().next)?;
+
+        Some((ValRef::new(first, self.shared.clone()),
+              ValRef::new(second, self.shared.clone())))
+    }
+
+    /// (Optionally) returns the next `K` elements of this cursor, a window
+    /// into the list, as an array.
+    ///
+    /// Only the first element is consumed, so consecutive windows overlap like
+    /// those of `slice::windows`. If there are fewer than `K` elements left,
+    /// returns `None` without consuming anything.
+    ///
+    /// `K` must be non-zero, which is checked at compile time.
+    pub fn next_window<'tail, const K: usize>(&'tail mut self)
+                                              -> Option<[ValRef<'tail, T, A>; K]> {
+        const { assert!(K != 0, "window size must be non-zero") };
+
+        // Only advance if there is a complete window
+        let mut last = next_real_node(&self.dummy.borrow_inner().next)?;
+
+        for _ in 1..K {
+            last = next_real_node(&last.borrow_inner().next)?;
+        }
+
+        // The cursor's dummy node is now directly after the first element, so
+        // it is skipped like any other dummy node
+        let mut next = Some(self.next()?.val_ref.node);
+        let shared = self.shared.clone();
+
+        Some(array::from_fn(|_| {
+            let node_ref = next.take().expect("window is incomplete");
+            next = next_real_node(&node_ref.borrow_inner().next);
+            ValRef::new(node_ref, shared.clone())
+        }))
+    }
+
+    /// Merges adjacent elements in a single pass over the remaining elements
+    /// of this cursor.
+    ///
+    /// For every element, `merge` is called with the element and its
+    /// successor. If it returns `true`, the successor is considered to be
+    /// merged into the element and is removed, and `merge` is called again
+    /// with the element's new successor. Afterwards, this cursor is at the end
+    /// of the list.
+    pub fn coalesce<F>(&mut self, mut merge: F) where F: FnMut(&mut T, &T) -> bool {
+        while let Some(node_ref) = self.next().map(|val| val.val_ref.node) {
+            // The cursor's dummy node is directly after the element
+            while let Some(next_ref) = next_real_node(&self.dummy.borrow_inner().next) {
+                let val = unsafe { &mut *val_ptr(&node_ref) };
+
+                if !merge(val, unsafe { &*val_ptr(&next_ref) }) {
+                    break;
+                }
+
+                release_slot(&self.shared, &next_ref);
+                discard(next_ref);
+            }
+        }
+    }
+
+    /// Inserts a detached node directly before the position of this cursor, so
+    /// it is not returned by this cursor, and returns a `ValRef` to it.
+    pub fn insert_node(&mut self, node: DetachedNode<T, A>) -> ValRef<'node, T, A> {
+        let node_ref = node.into_ref();
+        link_at(&self.dummy.borrow_inner().owning_link(), &node_ref);
+        ValRef::new(node_ref, self.shared.clone())
+    }
+
+    /// Turns this cursor into a `Marker`, which keeps the position of this
+    /// cursor without borrowing the list. The position can be resumed with
+    /// `TailList::resume`.
+    pub fn into_marker(self) -> Marker<T, A> {
+        let dummy = self.dummy.clone();
+
+        // The dummy node is owned by the marker from now on
+        mem::forget(self);
+        set_tag(&dummy, TAG_MARKER);
+
+        Marker { dummy }
+    }
 }
 
-impl<'node, T: 'node> ValRef<'node, T> {
-    /// Returns a new ValRef referencing the given node.
-    fn new(node: NodeRef<T>) -> ValRef<'node, T> {
+impl<'node, T: 'node, A: 'node + Allocator + Clone> ValRef<'node, T, A> {
+    /// Returns a new ValRef referencing the given node. `shared` is the shared
+    /// state of the list.
+    fn new(node: NodeRef<T, A>, shared: Ref<Shared<T, A>>) -> ValRef<'node, T, A> {
         ValRef {
-            node: node,
+            node,
+            shared,
             phantom: PhantomData,
         }
     }
 
     /// Inserts a new element before this element and returns a `ValRef` to the
     /// newly inserted element.
-    pub fn insert_before(&mut self, val: T) -> ValRef<'node, T> {
-        ValRef::new(insert_at(&self.node.borrow_inner().owning_link, Some(val)))
+    pub fn insert_before(&mut self, val: T) -> ValRef<'node, T, A> {
+        let owning_link = self.node.borrow_inner().owning_link();
+        let alloc = self.shared.borrow_inner().alloc.clone();
+        ValRef::new(insert_at(&owning_link, Some(val), alloc), self.shared.clone())
     }
 
     /// Inserts a new element after this element and returns a `ValRef` to the
     /// newly inserted element.
-    pub fn insert_after(&mut self, val: T) -> ValRef<'node, T> {
-        ValRef::new(insert_at(&self.node.borrow_inner().next, Some(val)))
+    pub fn insert_after(&mut self, val: T) -> ValRef<'node, T, A> {
+        let alloc = self.shared.borrow_inner().alloc.clone();
+        let node_ref = insert_at(&self.node.borrow_inner().next, Some(val), alloc);
+        ValRef::new(node_ref, self.shared.clone())
+    }
+
+    /// Inserts a detached node before this element and returns a `ValRef` to
+    /// it.
+    pub fn insert_node_before(&mut self, node: DetachedNode<T, A>) -> ValRef<'node, T, A> {
+        let node_ref = node.into_ref();
+        link_at(&self.node.borrow_inner().owning_link(), &node_ref);
+        ValRef::new(node_ref, self.shared.clone())
+    }
+
+    /// Inserts a detached node after this element and returns a `ValRef` to
+    /// it.
+    pub fn insert_node_after(&mut self, node: DetachedNode<T, A>) -> ValRef<'node, T, A> {
+        let node_ref = node.into_ref();
+        link_at(&self.node.borrow_inner().next, &node_ref);
+        ValRef::new(node_ref, self.shared.clone())
     }
 
     /// Removes this element from the list and returns it's value.
     pub fn remove(self) -> T {
-        let val = unlink(self.node);
+        release_slot(&self.shared, &self.node);
+        detach(&self.node);
+        unsafe { free_node(self.node) }
+    }
+
+    /// Returns a reference to this element, or `Error::Dummy` if this
+    /// references a dummy node.
+    pub fn try_get(&self) -> Result<&T, Error> {
+        self.check_dummy()?;
+        Ok(unsafe { &*val_ptr(&self.node) })
+    }
+
+    /// Returns a mutable reference to this element, or `Error::Dummy` if this
+    /// references a dummy node.
+    pub fn try_get_mut(&mut self) -> Result<&mut T, Error> {
+        self.check_dummy()?;
+        Ok(unsafe { &mut *val_ptr(&self.node) })
+    }
+
+    /// Like `insert_before`, but returns `Error::Dummy` instead of inserting if
+    /// this references a dummy node, or `Error::Alloc` (dropping `val`) if the
+    /// node cannot be allocated.
+    pub fn try_insert_before(&mut self, val: T) -> Result<ValRef<'node, T, A>, Error> {
+        self.check_dummy()?;
+
+        let owning_link = self.node.borrow_inner().owning_link();
+        let alloc = self.shared.borrow_inner().alloc.clone();
+        let node_ref = try_insert_at(&owning_link, val, alloc)?;
+        Ok(ValRef::new(node_ref, self.shared.clone()))
+    }
 
-        if let Some(val) = val {
-            return val;
+    /// Like `insert_after`, but returns `Error::Dummy` instead of inserting if
+    /// this references a dummy node, or `Error::Alloc` (dropping `val`) if the
+    /// node cannot be allocated.
+    pub fn try_insert_after(&mut self, val: T) -> Result<ValRef<'node, T, A>, Error> {
+        self.check_dummy()?;
+
+        let alloc = self.shared.borrow_inner().alloc.clone();
+        let node_ref = try_insert_at(&self.node.borrow_inner().next, val, alloc)?;
+        Ok(ValRef::new(node_ref, self.shared.clone()))
+    }
+
+    /// Removes this element from the list and returns it's value, or returns
+    /// `Error::Dummy` without removing anything if this references a dummy
+    /// node.
+    pub fn try_remove(self) -> Result<T, Error> {
+        self.check_dummy()?;
+        Ok(self.remove())
+    }
+
+    /// Returns `Error::Dummy` if this references a dummy node.
+    fn check_dummy(&self) -> Result<(), Error> {
+        if self.node.borrow_inner().is_dummy() {
+            return Err(Error::Dummy);
         }
 
-        unreachable!("cannot remove dummy node")
+        Ok(())
+    }
+
+    /// Replaces the value of this element with `val` and returns the old value.
+    pub fn replace(&mut self, val: T) -> T {
+        mem::replace(&mut **self, val)
+    }
+
+    /// Replaces the value of this element with its default value and returns
+    /// the old value.
+    pub fn take(&mut self) -> T where T: Default {
+        mem::take(&mut **self)
+    }
+
+    /// Swaps the values of this element and the element of `other`.
+    pub fn swap_values(&mut self, other: &mut ValRef<'node, T, A>) {
+        mem::swap(&mut **self, &mut **other)
+    }
+
+    /// Returns this element pinned. Its address does not change until it is
+    /// removed from the list.
+    ///
+    /// # Safety
+    ///
+    /// Once pinned, the element must not be moved until it is dropped, as
+    /// required by `Pin::new_unchecked`. It is moved by `replace`, `take`,
+    /// `swap_values`, `remove`, `DetachedNode::into_inner` or by moving it out
+    /// of a mutable reference. It is dropped in place by dropping the list or
+    /// a `DetachedNode`, or by `coalesce`.
+    pub unsafe fn as_pin_mut(&mut self) -> Pin<&mut T> {
+        Pin::new_unchecked(&mut **self)
+    }
+
+    /// Returns a `MappedValRef` to the part of this element selected by `f`,
+    /// e.g. one of its fields.
+    ///
+    /// The returned value cannot be used to remove the element, so it will
+    /// not be removed until the list is borrowed mutably again.
+    pub fn map<U, F>(self, f: F) -> MappedValRef<'node, U>
+        where F: FnOnce(&mut T) -> &mut U
+    {
+        MappedValRef { val: f(unsafe { &mut *val_ptr(&self.node) }) }
+    }
+
+    /// Removes this element from the list without freeing its node, which can
+    /// be inserted into a list again without allocating.
+    pub fn detach(self) -> DetachedNode<T, A> {
+        detach(&self.node);
+
+        release_slot(&self.shared, &self.node);
+        DetachedNode { node: self.node }
+    }
+
+    /// Returns a `Handle` to this element, which may be resolved again after
+    /// this `ValRef` has gone out of scope.
+    ///
+    /// If this is the first handle to the element, the element is assigned a
+    /// slot and an id.
+    #[cfg(target_has_atomic = "ptr")]
+    pub fn handle(&self) -> Handle<T, A> {
+        let (slot, id) = handle_slot(&self.shared, &self.node);
+
+        Handle {
+            slot,
+            list: self.shared.borrow_inner().list,
+            id,
+            phantom: PhantomData,
+        }
     }
 }
 
-impl<'node, 'tail, T: 'node + 'tail> TailValRef<'node, 'tail, T> {
+impl<'node, 'tail, T: 'node + 'tail, A: 'node + 'tail + Allocator + Clone>
+    TailValRef<'node, 'tail, T, A>
+{
     /// Returns a reference to a `ValRef` to the first node owned by `self`, as
     /// well as a `Cursor` owning the rest of the nodes owned by `self`.
     ///
     /// After both items have gone out of scope, this method may be called
     /// again.
-    pub fn tail<'slf>(&'slf mut self) -> (&'slf ValRef<'node, T>,
-                                          Cursor<'slf, T>) {
-        let csr = Cursor::new(&self.val_ref.node.borrow_inner().next.new_ref());
+    pub fn tail<'slf>(&'slf mut self) -> (&'slf ValRef<'node, T, A>,
+                                          Cursor<'slf, T, A>) {
+        let csr = tail_cursor(&self.val_ref);
         (&self.val_ref, csr)
     }
 
@@ -239,138 +1127,393 @@ impl<'node, 'tail, T: 'node + 'tail> TailValRef<'node, 'tail, T> {
     ///
     /// This method consumes `self`. The `Cursor` who returned this may be used
     /// again after the returned cursor has gone out of scope.
-    pub fn into_tail(self) -> (ValRef<'node, T>, Cursor<'tail, T>) {
-        let csr = Cursor::new(&self.val_ref.node.borrow_inner().next.new_ref());
+    pub fn into_tail(self) -> (ValRef<'node, T, A>, Cursor<'tail, T, A>) {
+        let csr = tail_cursor(&self.val_ref);
         (self.val_ref, csr)
     }
 
     /// Turns `self` into a `ValRef` to the first node owned by `self`. The
     /// `Cursor` who returned this may be used again after this method has been
     /// called.
-    pub fn into_passive(self) -> ValRef<'node, T> {
+    pub fn into_passive(self) -> ValRef<'node, T, A> {
         self.val_ref
     }
 
     /// Inserts a new element before this element and returns a `ValRef` to the
     /// newly inserted element.
-    pub fn insert_before(&mut self, val: T) -> ValRef<'node, T> {
+    pub fn insert_before(&mut self, val: T) -> ValRef<'node, T, A> {
         self.val_ref.insert_before(val)
     }
 
     /// Inserts a new element after this element and returns a `ValRef` to the
     /// newly inserted element.
-    pub fn insert_after(&mut self, val: T) -> ValRef<'node, T> {
+    pub fn insert_after(&mut self, val: T) -> ValRef<'node, T, A> {
         self.val_ref.insert_after(val)
     }
 
+    /// Inserts a detached node before this element and returns a `ValRef` to
+    /// it.
+    pub fn insert_node_before(&mut self, node: DetachedNode<T, A>) -> ValRef<'node, T, A> {
+        self.val_ref.insert_node_before(node)
+    }
+
+    /// Inserts a detached node after this element and returns a `ValRef` to
+    /// it.
+    pub fn insert_node_after(&mut self, node: DetachedNode<T, A>) -> ValRef<'node, T, A> {
+        self.val_ref.insert_node_after(node)
+    }
+
     /// Removes this element from the list and returns it's value.
     pub fn remove(self) -> T {
         self.val_ref.remove()
     }
+
+    /// Removes this element from the list without freeing its node.
+    pub fn detach(self) -> DetachedNode<T, A> {
+        self.val_ref.detach()
+    }
+}
+
+impl<'node, U: 'node> MappedValRef<'node, U> {
+    /// Returns a `MappedValRef` to the part of this value selected by `f`.
+    pub fn map<V, F>(self, f: F) -> MappedValRef<'node, V>
+        where F: FnOnce(&mut U) -> &mut V
+    {
+        MappedValRef { val: f(self.val) }
+    }
+}
+
+impl<T, A: Allocator + Clone> DetachedNode<T, A> {
+    /// Frees the node and returns its value.
+    pub fn into_inner(self) -> T {
+        unsafe { free_node(self.into_ref()) }
+    }
+
+    /// Returns a reference to the node, which is owned by the caller from now
+    /// on.
+    fn into_ref(self) -> NodeRef<T, A> {
+        let node_ref = self.node.clone();
+        mem::forget(self);
+        node_ref
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////////////
 // FUNCTIONS
 ////////////////////////////////////////////////////////////////////////////////
 
-/// Inserts a new node into the list, directly at / after `link`.
-fn insert_at<T, L: OwnRef<Inner=Link<T>>>(link: &L, val: Option<T>) -> NodeRef<T> {
-    // Create a `NodeOwn` for the new value
-    let node = Own::new(Node::new(val, link.new_ref()));
+/// Inserts a new node, allocated from `alloc`, into the list, directly at /
+/// after `link`.
+fn insert_at<T, A, L>(link: &L, val: Option<T>, alloc: A) -> NodeRef<T, A>
+    where A: Allocator + Clone, L: OwnRef<Inner=Link<T, A>>
+{
+    // Allocate a new node for the value
+    let node_ref = alloc_node(val, link.new_ref(), alloc);
+    link_at(link, &node_ref);
 
-    // Move the tail of `link` to `node.next`
-    let link: &mut Link<T> = link.borrow_inner_mut();
+    // Return a reference to the newly created node
+    node_ref
+}
 
-    {
-        let next: &mut Link<T> = node.borrow_inner().next.borrow_inner_mut();
+/// Like `insert_at` for a new non-dummy node, but returns `Error::Alloc`
+/// (dropping `val`) instead of aborting if the node cannot be allocated.
+fn try_insert_at<T, A, L>(link: &L, val: T, alloc: A) -> Result<NodeRef<T, A>, Error>
+    where A: Allocator + Clone, L: OwnRef<Inner=Link<T, A>>
+{
+    let node_ref = try_alloc_node(val, link.new_ref(), alloc)?;
+    link_at(link, &node_ref);
+    Ok(node_ref)
+}
 
-        mem::swap(link, next);
+/// Inserts the given unlinked node into the list, directly at / after `link`.
+fn link_at<T, A, L>(link: &L, node_ref: &NodeRef<T, A>)
+    where L: OwnRef<Inner=Link<T, A>>
+{
+    // Make `link` link to the node and move the tail of `link` to `node.next`
+    let tail = link.replace_inner(Link(Some(node_ref.clone())));
+    node_ref.borrow_inner().next.replace_inner(tail);
+
+    // Fix the `owning_link` of the node and of the node originally linked to
+    // by `link` (which is now linked to by node.next)
+    fixup_owning_link(link);
+    fixup_owning_link(&node_ref.borrow_inner().next);
+}
+
+/// Moves `node` out of its current place in the list and re-inserts it
+/// directly after `target`, which must be one of its successors.
+///
+/// Compared to swapping the places of two nodes, this only writes the three
+/// links and three owning links which actually change.
+fn move_after<T, A>(node: &NodeRef<T, A>, target: &NodeRef<T, A>) {
+    check_owning_link(node);
+    check_owning_link(target);
+
+    // Unlink `node`, its stale `next` link is overwritten below
+    // owning -> Node -> Next
+    let owning_link = node.borrow_inner().owning_link();
+    let next = node.borrow_inner().next.borrow_inner().opt_node_ref();
+    owning_link.replace_inner(Link(next));
+    fixup_owning_link(&owning_link);
+    // owning -> Next
+
+    // Insert `node` after `target`
+    // target.next -> Tail
+    let tail = target.borrow_inner().next.replace_inner(Link(Some(node.clone())));
+    node.borrow_inner().next.replace_inner(tail);
+    fixup_owning_link(&target.borrow_inner().next);
+    fixup_owning_link(&node.borrow_inner().next);
+    // target.next -> Node -> Tail
+
+    check_owning_link(node);
+    check_owning_link(target);
+}
+
+/// Unlinks / removes the given node from the list and frees it. Its value is
+/// dropped in place.
+fn discard<T, A: Allocator + Clone>(node_ref: NodeRef<T, A>) {
+    detach(&node_ref);
+
+    unsafe { drop_node(node_ref) }
+}
+
+/// Unlinks / removes the given node from the list without freeing it. The
+/// `owning_link` of the node is left dangling.
+fn detach<T, A>(node_ref: &NodeRef<T, A>) {
+    check_owning_link(node_ref);
+
+    // A reference to the link owning the node to remove
+    let owning_link: LinkRef<T, A> = node_ref.borrow_inner().owning_link();
+
+    // Remove the node from the list
+    // owning -> This, next -> Next
+    let next = node_ref.borrow_inner().next.replace_inner(Link::new());
+    // owning -> This, next -> None
+    owning_link.replace_inner(next);
+    // owning -> Next, next -> None
+
+    fixup_owning_link(&owning_link);
+}
+
+/// Allocates a new node from `alloc`, which is a dummy node iff `val` is
+/// `None`, and returns a reference to its header. The node must eventually be
+/// freed with `free_node` or `drop_node`.
+fn alloc_node<T, A>(val: Option<T>, owning_link: LinkRef<T, A>, alloc: A)
+    -> NodeRef<T, A> where A: Allocator + Clone
+{
+    match val {
+        Some(val) => try_alloc_node(val, owning_link, alloc)
+            .unwrap_or_else(|_| handle_alloc_error(Layout::new::<NodeOwn<T, A>>())),
+        None => {
+            let header = Header::new(owning_link, TAG_CURSOR, alloc.clone());
+            let dummy = Box::new_in(Own::new(header), alloc);
+            Ref::new(Box::into_raw_with_allocator(dummy).0 as *mut Header<T, A>)
+        }
     }
+}
 
-    // Make `link` link to the new node
-    *link = Link::new_to_node(node);
+/// Allocates a new non-dummy node from `alloc` like `alloc_node`, but returns
+/// `Error::Alloc` (dropping `val`) if `alloc` fails.
+fn try_alloc_node<T, A>(val: T, owning_link: LinkRef<T, A>, alloc: A)
+    -> Result<NodeRef<T, A>, Error> where A: Allocator + Clone
+{
+    let header = Header::new(owning_link, TAG_NODE, alloc.clone());
+    let node = Node { header, val, slot: NO_SLOT };
+    let node = Box::try_new_in(Own::new(node), alloc).map_err(|_| Error::Alloc)?;
+
+    // A `Node` starts with its header, so a pointer to the node is a pointer
+    // to the header
+    Ok(Ref::new(Box::into_raw_with_allocator(node).0 as *mut Header<T, A>))
+}
 
-    // Get a reference to the newly created node
-    let node_ref = link.opt_node_ref()
-        .expect("the option was just initialized to some");
+/// Frees the given node, which must have been returned by `alloc_node` and
+/// must not be linked to anymore, and returns its value. The node must not be
+/// a dummy node.
+unsafe fn free_node<T, A: Allocator + Clone>(node_ref: NodeRef<T, A>) -> T {
+    debug_assert!(!node_ref.borrow_inner().is_dummy(), "dummy node has no value");
 
-    // Fix the `owning_link` of node originally linked to by `link` (which is
-    // now linked to by node.next)
-    fixup_owning_link(&node_ref.borrow_inner().next);
+    let alloc = node_ref.borrow_inner().alloc.clone();
+    let ptr = node_ref.get_mut_ptr() as *mut NodeOwn<T, A>;
+    Box::into_inner(Box::from_raw_in(ptr, alloc)).0.into_inner().val
+}
 
-    // Return a reference to the newly created node
-    node_ref
+/// Frees the given node, which must already be unlinked, without moving its
+/// value: the value of a node which is not a dummy node is dropped in place.
+unsafe fn drop_node<T, A: Allocator + Clone>(node_ref: NodeRef<T, A>) {
+    let alloc = node_ref.borrow_inner().alloc.clone();
+
+    if node_ref.borrow_inner().is_dummy() {
+        let ptr = node_ref.get_mut_ptr() as *mut HeaderOwn<T, A>;
+        drop(Box::from_raw_in(ptr, alloc));
+    } else {
+        let ptr = node_ref.get_mut_ptr() as *mut NodeOwn<T, A>;
+        drop(Box::from_raw_in(ptr, alloc));
+    }
+}
+
+/// Returns a pointer to the value of the given node, which must not be a
+/// dummy node.
+fn val_ptr<T, A>(node_ref: &NodeRef<T, A>) -> *mut T {
+    debug_assert!(!node_ref.borrow_inner().is_dummy(), "dummy node has no value");
+
+    unsafe { &raw mut (*(node_ref.get_mut_ptr() as *mut Node<T, A>)).val }
 }
 
-/// Swap the places of two nodes in the list
-fn swap_places<T>(a: &NodeRef<T>, b: &NodeRef<T>) {
-    // Swap the actual nodes (in the owning links)
-    let a_link = a.borrow_inner().owning_link.borrow_inner_mut();
-    let b_link = b.borrow_inner().owning_link.borrow_inner_mut();
+/// Returns a pointer to the slot index of the given node, which must not be a
+/// dummy node.
+fn slot_ptr<T, A>(node_ref: &NodeRef<T, A>) -> *mut u32 {
+    debug_assert!(!node_ref.borrow_inner().is_dummy(), "dummy node has no slot");
 
-    mem::swap(a_link, b_link);
+    unsafe { &raw mut (*(node_ref.get_mut_ptr() as *mut Node<T, A>)).slot }
+}
 
-    // Swap the next links
-    let a_next = a.borrow_inner().next.borrow_inner_mut();
-    let b_next = b.borrow_inner().next.borrow_inner_mut();
+/// Returns the slot and the id of the element at `node_ref`, in the list whose
+/// `shared` state is given. If it has no slot yet, it is given a vacant slot
+/// and the next id of the list, and the list is assigned an id as well, if it
+/// has none yet.
+#[cfg(target_has_atomic = "ptr")]
+fn handle_slot<T, A>(shared: &Ref<Shared<T, A>>, node_ref: &NodeRef<T, A>) -> (u32, u64)
+    where A: Allocator + Clone
+{
+    // The shared state is only borrowed during this call
+    let shared = unsafe { &mut *shared.get_mut_ptr() };
+
+    if shared.list == 0 {
+        shared.list = unique_id();
+    }
 
-    mem::swap(a_next, b_next);
+    match unsafe { *slot_ptr(node_ref) } {
+        NO_SLOT => {
+            let id = next_id(shared);
+            (occupy_slot(shared, node_ref, id), id)
+        }
+        slot => (slot, slot_id(shared, slot)),
+    }
+}
 
-    // Fix up all owning links
-    fixup_owning_link(&a.borrow_inner().owning_link);
-    fixup_owning_link(&b.borrow_inner().owning_link);
-    fixup_owning_link(&a.borrow_inner().next);
-    fixup_owning_link(&b.borrow_inner().next);
+/// Returns the next id of the list whose `shared` state is given, which is
+/// never returned again.
+#[cfg(target_has_atomic = "ptr")]
+fn next_id<T, A: Allocator + Clone>(shared: &mut Shared<T, A>) -> u64 {
+    let id = shared.next;
+    shared.next = id.checked_add(1).expect("list ran out of element ids");
+    id
 }
 
-/// Unlinks / removes the given node from the list and returns its optional
-/// value.
-fn unlink<T>(node_ref: NodeRef<T>) -> Option<T> {
-    // A mutable borrow of the next link of the node to remove
-    let next: &mut Link<T> = node_ref.borrow_inner().next
-        .borrow_inner_mut();
+/// Returns the id of the element in the given slot, which must not be vacant.
+#[cfg(target_has_atomic = "ptr")]
+fn slot_id<T, A: Allocator + Clone>(shared: &Shared<T, A>, slot: u32) -> u64 {
+    match shared.slots[slot as usize] {
+        Slot::Element { id, .. } => id,
+        Slot::Vacant(_) => unreachable!("element has a vacant slot"),
+    }
+}
 
-    // A reference to the link owning the node to remove
-    let owning_link_ref: LinkRef<T> = node_ref.borrow_inner().owning_link
-        .clone();
+/// Gives the element at `node_ref`, which must not have a slot yet, a vacant
+/// slot with the given id, and returns the index of the slot.
+#[cfg(target_has_atomic = "ptr")]
+fn occupy_slot<T, A>(shared: &mut Shared<T, A>, node_ref: &NodeRef<T, A>, id: u64) -> u32
+    where A: Allocator + Clone
+{
+    if shared.vacant == NO_SLOT {
+        shared.vacant = u32::try_from(shared.slots.len()).ok()
+            .filter(|&len| len != NO_SLOT)
+            .expect("list ran out of slots");
+        shared.slots.push(Slot::Vacant(NO_SLOT));
+    }
 
-    // A mutable borrow of the link linking to the node to remove
-    let owning_link: &mut Link<T> = owning_link_ref.borrow_inner_mut();
+    let slot = shared.vacant;
+    let element = Slot::Element { node: node_ref.clone(), id };
 
-    // A temporary owning link
-    let tmp_link_own = Own::new(Link::new());
+    match mem::replace(&mut shared.slots[slot as usize], element) {
+        Slot::Vacant(next) => shared.vacant = next,
+        Slot::Element { .. } => unreachable!("vacant slot is occupied"),
+    }
 
-    // Remove the node from the list
-    {
-        // A mutable borrow of the link owned by `tmp_link_own`
-        let tmp_link: &mut Link<T> = tmp_link_own.borrow_inner_mut();
+    unsafe { *slot_ptr(node_ref) = slot; }
+    slot
+}
 
-        // tmp -> None, owning -> This, next -> Next
-        mem::swap(tmp_link, owning_link);
-        // tmp -> This, owning -> None, next -> Next
-        mem::swap(owning_link, next);
-        // tmp -> This, owning -> Next, next -> None
+/// Frees the slot of the element at `node_ref`, if it has one, in the list
+/// whose `shared` state is given, so all handles to the element become stale.
+/// Must be called whenever an element is removed from a list.
+fn release_slot<T, A>(shared: &Ref<Shared<T, A>>, node_ref: &NodeRef<T, A>)
+    where A: Allocator + Clone
+{
+    let slot = unsafe { *slot_ptr(node_ref) };
 
-        fixup_owning_link(&owning_link_ref);
+    if slot == NO_SLOT {
+        return;
     }
 
-    // Extract the value now owned by `tmp_link_own`
-    unsafe {
-        let val: NodeOwn<T> = *tmp_link_own.0.into_inner().0
-            .expect("the option was just set to some");
+    // The shared state is only borrowed during this call
+    let shared = unsafe { &mut *shared.get_mut_ptr() };
+    shared.slots[slot as usize] = Slot::Vacant(shared.vacant);
+    shared.vacant = slot;
+
+    unsafe { *slot_ptr(node_ref) = NO_SLOT; }
+}
+
+/// Returns a new non-zero id, which is never returned again, to tell apart
+/// lists and timer wheels, including ones which have been dropped.
+#[cfg(target_has_atomic = "ptr")]
+fn unique_id() -> usize {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Sets the tag of the given node (see `Header`).
+fn set_tag<T, A>(node_ref: &NodeRef<T, A>, tag: usize) {
+    let link = node_ref.borrow_inner().owning_link.map_addr(|addr| addr & !TAG_MASK);
+    Header::set_owning_link(node_ref, link, tag);
+}
 
-        val.0.into_inner().val
+/// Sets the address of the `owning_link` of the given (unlinked) node to null,
+/// keeping its tag (see `Header::is_orphan`).
+fn clear_owning_link<T, A>(node_ref: &NodeRef<T, A>) {
+    let tag = node_ref.borrow_inner().tag();
+    Header::set_owning_link(node_ref, ptr::null_mut(), tag);
+}
+
+/// Returns the first non-dummy node linked to by `link` or its successors.
+fn next_real_node<T, A, L: OwnRef<Inner=Link<T, A>>>(link: &L) -> Option<NodeRef<T, A>> {
+    let mut node_ref = link.borrow_inner().opt_node_ref()?;
+
+    while node_ref.borrow_inner().is_dummy() {
+        node_ref = node_ref.borrow_inner().next.borrow_inner().opt_node_ref()?;
     }
+
+    Some(node_ref)
+}
+
+/// Returns a new cursor over the tail of the given element.
+fn tail_cursor<'node, 'a, T, A>(val_ref: &ValRef<'a, T, A>) -> Cursor<'node, T, A>
+    where A: Allocator + Clone
+{
+    let next = val_ref.node.borrow_inner().next.new_ref();
+    Cursor::new(&next, val_ref.shared.clone())
 }
 
 /// Given a link, if this link links to a node, ensures that the node's
 /// `owning_link` points to the given link.
-fn fixup_owning_link<T, L: OwnRef<Inner=Link<T>>>(link: &L) {
+fn fixup_owning_link<T, A, L: OwnRef<Inner=Link<T, A>>>(link: &L) {
     let opt_node_ref = link.borrow_inner().opt_node_ref();
 
     if let Some(node_ref) = opt_node_ref {
-        let node = node_ref.borrow_inner_mut();
-        node.owning_link = link.new_ref();
+        let tag = node_ref.borrow_inner().tag();
+        Header::set_owning_link(&node_ref, link.get_mut_ptr(), tag);
+    }
+}
+
+/// In debug builds, panics if the given node is not linked to by its
+/// `owning_link`, i.e. if the list has been corrupted.
+fn check_owning_link<T, A>(node_ref: &NodeRef<T, A>) {
+    if cfg!(debug_assertions) {
+        let owning_link = node_ref.borrow_inner().owning_link();
+        let linked = owning_link.borrow_inner().opt_node_ref();
+
+        assert!(linked.map(|l| l.get_mut_ptr()) == Some(node_ref.get_mut_ptr()),
+                "corrupted list: node is not linked to by its owning link");
     }
 }
 
@@ -379,6 +1522,10 @@ fn fixup_owning_link<T, L: OwnRef<Inner=Link<T>>>(link: &L) {
 ////////////////////////////////////////////////////////////////////////////////
 
 /// A trait abstracting over `Own` and `Ref`.
+///
+/// There may be many `Ref`s to the same value, so the inner value is never
+/// borrowed mutably. Instead, it is modified through raw pointers, which keeps
+/// all other `Ref`s valid (under both Stacked and Tree Borrows).
 trait OwnRef {
     /// The type encapsulated by this `Own` or `Ref`.
     type Inner;
@@ -388,17 +1535,18 @@ trait OwnRef {
 
     /// Returns a new `Ref` to the inner value.
     fn new_ref(&self) -> Ref<Self::Inner> {
-        Ref(self.get_mut_ptr())
+        Ref::new(self.get_mut_ptr())
     }
 
-    /// Borrows the inner value.
+    /// Borrows the inner value. The borrow must end before the inner value is
+    /// modified.
     fn borrow_inner(&self) -> &Self::Inner {
         unsafe { & *self.get_mut_ptr() }
     }
 
-    /// Borrows the inner value mutably.
-    fn borrow_inner_mut(&self) -> &mut Self::Inner {
-        unsafe { &mut *self.get_mut_ptr() }
+    /// Replaces the inner value with `val` and returns the old value.
+    fn replace_inner(&self, val: Self::Inner) -> Self::Inner {
+        unsafe { ptr::replace(self.get_mut_ptr(), val) }
     }
 }
 
@@ -406,6 +1554,30 @@ trait OwnRef {
 // TRAIT IMPLS
 ////////////////////////////////////////////////////////////////////////////////
 
+impl<T> Default for TailList<T> {
+    fn default() -> TailList<T> {
+        TailList::new()
+    }
+}
+
+impl<T, A: Allocator + Clone> Drop for TailList<T, A> {
+    fn drop(&mut self) {
+        while let Some(node_ref) = self.head.borrow_inner().opt_node_ref() {
+            if node_ref.borrow_inner().tag() == TAG_MARKER {
+                // The dummy node is freed by its marker, which is told that the
+                // list is gone by a null `owning_link`
+                detach(&node_ref);
+                clear_owning_link(&node_ref);
+            } else {
+                discard(node_ref);
+            }
+        }
+
+        let head = self.head.get_mut_ptr() as *mut LinkOwn<T, A>;
+        unsafe { drop(Box::from_raw_in(head, self.shared.alloc.clone())); }
+    }
+}
+
 impl<T> OwnRef for Own<T> {
     type Inner = T;
 
@@ -415,7 +1587,7 @@ impl<T> OwnRef for Own<T> {
 impl<T> OwnRef for Ref<T> {
     type Inner = T;
 
-    fn get_mut_ptr(&self) -> *mut T { self.0 }
+    fn get_mut_ptr(&self) -> *mut T { self.0.as_ptr() }
 }
 
 impl<T> Clone for Ref<T> {
@@ -424,35 +1596,136 @@ impl<T> Clone for Ref<T> {
     }
 }
 
-impl <'node, T: 'node> Drop for Cursor<'node, T> {
+impl<'node, T: 'node, A: 'node + Allocator + Clone> Iterator
+    for ReadCursor<'node, T, A>
+{
+    type Item = &'node T;
+
+    fn next(&mut self) -> Option<&'node T> {
+        let node_ref = self.next.take()?;
+        self.next = next_real_node(&node_ref.borrow_inner().next);
+        Some(unsafe { &*val_ptr(&node_ref) })
+    }
+}
+
+impl<'node, T: 'node, A: 'node + Allocator + Clone> Clone
+    for ReadCursor<'node, T, A>
+{
+    fn clone(&self) -> ReadCursor<'node, T, A> {
+        ReadCursor {
+            next: self.next.clone(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+#[cfg(target_has_atomic = "ptr")]
+impl<T, A> Clone for Handle<T, A> {
+    fn clone(&self) -> Handle<T, A> {
+        *self
+    }
+}
+
+#[cfg(target_has_atomic = "ptr")]
+impl<T, A> Copy for Handle<T, A> {}
+
+#[cfg(target_has_atomic = "ptr")]
+impl<T, A> PartialEq for Handle<T, A> {
+    fn eq(&self, other: &Handle<T, A>) -> bool {
+        self.slot == other.slot && self.list == other.list && self.id == other.id
+    }
+}
+
+#[cfg(target_has_atomic = "ptr")]
+impl<T, A> Eq for Handle<T, A> {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Dummy => f.write_str("the node is a dummy node"),
+            Error::Stale => f.write_str("the element is no longer part of the list"),
+            Error::Alloc => f.write_str("the node could not be allocated"),
+            Error::DuplicateId => f.write_str("the snapshot contains an id more than once"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+impl <'node, T: 'node, A: 'node + Allocator + Clone> Drop
+    for Cursor<'node, T, A>
+{
+    fn drop(&mut self) {
+        discard(self.dummy.clone());
+    }
+}
+
+impl<T, A: Allocator + Clone> Drop for Marker<T, A> {
     fn drop(&mut self) {
-        unlink(self.dummy.clone());
+        if self.dummy.borrow_inner().is_orphan() {
+            unsafe { drop_node(self.dummy.clone()); }
+        } else {
+            discard(self.dummy.clone());
+        }
     }
 }
 
-impl<'node, T: 'node> Deref for ValRef<'node, T> {
+impl<'node, U: 'node> Deref for MappedValRef<'node, U> {
+    type Target = U;
+
+    fn deref(&self) -> &U {
+        self.val
+    }
+}
+
+impl<'node, U: 'node> DerefMut for MappedValRef<'node, U> {
+    fn deref_mut(&mut self) -> &mut U {
+        self.val
+    }
+}
+
+impl<T, A: Allocator + Clone> Drop for DetachedNode<T, A> {
+    fn drop(&mut self) {
+        unsafe { drop_node(self.node.clone()); }
+    }
+}
+
+impl<T, A: Allocator + Clone> Deref for DetachedNode<T, A> {
     type Target = T;
 
     fn deref(&self) -> &T {
-        if let Some(ref val) = self.node.borrow_inner().val {
-            return val;
-        }
-
-        unreachable!("cannot deref dummy node");
+        unsafe { &*val_ptr(&self.node) }
     }
 }
 
-impl<'node, T: 'node> DerefMut for ValRef<'node, T> {
+impl<T, A: Allocator + Clone> DerefMut for DetachedNode<T, A> {
     fn deref_mut(&mut self) -> &mut T {
-        if let Some(ref mut val) = self.node.borrow_inner_mut().val {
-            return val;
-        }
+        unsafe { &mut *val_ptr(&self.node) }
+    }
+}
+
+impl<'node, T: 'node, A: 'node + Allocator + Clone> Deref
+    for ValRef<'node, T, A>
+{
+    type Target = T;
 
-        unreachable!("cannot deref dummy node");
+    fn deref(&self) -> &T {
+        unsafe { &*val_ptr(&self.node) }
+    }
+}
+
+impl<'node, T: 'node, A: 'node + Allocator + Clone> DerefMut
+    for ValRef<'node, T, A>
+{
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *val_ptr(&self.node) }
     }
 }
 
-impl<'node, 'tail, T: 'node + 'tail> Deref for TailValRef<'node, 'tail, T> {
+impl<'node, 'tail, T: 'node + 'tail, A: 'node + 'tail + Allocator + Clone> Deref
+    for TailValRef<'node, 'tail, T, A>
+{
     type Target = T;
 
     fn deref(&self) -> &T {
@@ -460,7 +1733,9 @@ impl<'node, 'tail, T: 'node + 'tail> Deref for TailValRef<'node, 'tail, T> {
     }
 }
 
-impl<'node, 'tail, T: 'node + 'tail> DerefMut for TailValRef<'node, 'tail, T> {
+impl<'node, 'tail, T: 'node + 'tail, A: 'node + 'tail + Allocator + Clone> DerefMut
+    for TailValRef<'node, 'tail, T, A>
+{
     fn deref_mut(&mut self) -> &mut T {
         self.val_ref.deref_mut()
     }
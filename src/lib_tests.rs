@@ -1,15 +1,27 @@
+use std::alloc::Layout;
+use std::cell::{Cell, UnsafeCell};
 use std::fmt::Debug;
-use super::{swap_places, TailList, Link, LinkOwn, OwnRef};
+use std::marker::PhantomPinned;
+use std::mem;
+use std::pin::Pin;
+use std::ptr::{self, NonNull};
+use std::rc::Rc;
+use std::slice;
+use allocator_api2::alloc::{AllocError, Allocator, Global};
+use proptest::prelude::*;
+use super::{move_after, val_ptr, DetachedNode, Error, Marker, TailList, Cursor, ValRef, Header, Node, NodeRef, Ref, Link, LinkOwn,
+            OwnRef};
 
 /// List validation utility, see method documentation
-struct Validator<T>(*const LinkOwn<T>);
+struct Validator<T, A: Allocator = Global>(*const LinkOwn<T, A>);
 
-impl<T: Debug> Validator<T> {
+impl<T: Debug, A: Allocator + Clone> Validator<T, A> {
     /// Create a new `Validator`.
     ///
-    /// The list which should be validated **MUST NOT BE MOVED**.
-    fn new(list: &TailList<T>) -> Validator<T> {
-        Validator(&list.head)
+    /// The list which should be validated must outlive the validator, but may
+    /// be moved, since its head link is allocated separately.
+    fn new(list: &TailList<T, A>) -> Validator<T, A> {
+        Validator(list.head.get_mut_ptr() as *const LinkOwn<T, A>)
     }
 
     /// Validate the list, `line` is used in the error message.
@@ -19,8 +31,8 @@ impl<T: Debug> Validator<T> {
             let mut next_ref_opt = (*this_link).borrow_inner().opt_node_ref();
 
             while let Some(next_ref) = next_ref_opt {
-                let l_ptr: *mut Link<T> = (*this_link).0.get();
-                let ol_ptr: *mut Link<T> = next_ref.borrow_inner().owning_link.0;
+                let l_ptr: *mut Link<T, A> = (*this_link).0.get();
+                let ol_ptr: *mut Link<T, A> = next_ref.borrow_inner().owning_link().get_mut_ptr();
 
                 assert!(l_ptr == ol_ptr, "invalid list ptr at line {}", line);
 
@@ -30,6 +42,32 @@ impl<T: Debug> Validator<T> {
         }
     }
 
+    /// Returns the values of all nodes of the list, in order, where dummy nodes
+    /// are represented by `None`.
+    fn contents(&self) -> Vec<Option<T>> where T: Clone {
+        unsafe {
+            let mut vals = Vec::new();
+            let mut next_ref_opt = (*self.0).borrow_inner().opt_node_ref();
+
+            while let Some(next_ref) = next_ref_opt {
+                vals.push(Validator::val(&next_ref).cloned());
+                next_ref_opt = next_ref.borrow_inner().next.borrow_inner()
+                    .opt_node_ref();
+            }
+
+            vals
+        }
+    }
+
+    /// Returns the value of the given node, or `None` if it is a dummy node.
+    unsafe fn val<'a>(node_ref: &NodeRef<T, A>) -> Option<&'a T> {
+        if node_ref.borrow_inner().is_dummy() {
+            None
+        } else {
+            Some(&*val_ptr(node_ref))
+        }
+    }
+
     /// Dumps the complete list of this validator to stdout.
     ///
     /// This function may be used when debugging failing tests.
@@ -40,23 +78,64 @@ impl<T: Debug> Validator<T> {
     }
 
     /// Dump a node and its tail recursivly.
-    fn dump_tail(this_link: *const LinkOwn<T>) {
+    fn dump_tail(this_link: *const LinkOwn<T, A>) {
         unsafe {
             let next_ref_opt = (*this_link).borrow_inner().opt_node_ref();
             println!("OwnLink @ {:?}", this_link);
 
             if let Some(next_ref) = next_ref_opt {
                 println!("Linking to OwnNode @ {:?}", next_ref.get_mut_ptr());
-                println!("With value: {:?}", next_ref.borrow_inner().val);
+                println!("With value: {:?}", Validator::val(&next_ref));
                 println!("With owning_link to {:?}", next_ref.borrow_inner()
-                    .owning_link.get_mut_ptr());
-                println!("");
+                    .owning_link().get_mut_ptr());
+                println!();
                 Validator::dump_tail(&next_ref.borrow_inner().next);
             }
         }
     }
 }
 
+/// An allocator which counts the number of currently live allocations.
+#[derive(Clone)]
+struct CountingAlloc(Rc<Cell<usize>>);
+
+unsafe impl Allocator for CountingAlloc {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.0.set(self.0.get() + 1);
+        Global.allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.0.set(self.0.get() - 1);
+        Global.deallocate(ptr, layout)
+    }
+}
+
+/// An allocator which fails once the given number of allocations has been
+/// used up.
+#[derive(Clone)]
+struct LimitedAlloc(Rc<Cell<usize>>);
+
+unsafe impl Allocator for LimitedAlloc {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        match self.0.get() {
+            0 => Err(AllocError),
+            left => {
+                self.0.set(left - 1);
+                Global.allocate(layout)
+            }
+        }
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        Global.deallocate(ptr, layout)
+    }
+}
+
+/// The number of elements used by most tests. Miri is much slower than native
+/// execution, so fewer elements are used when running under it.
+const N: u64 = if cfg!(miri) { 32 } else { 1024 };
+
 /// Call `$v.validate()` with the current line number iff compiling without the
 /// `test_no_validate` feature.
 macro_rules! validate {
@@ -64,24 +143,66 @@ macro_rules! validate {
 }
 
 #[test]
-fn it_swaps_places() {
-    println!("");
-
+fn it_moves_after() {
     let mut list = TailList::new();
     let v = Validator::new(&list);
 
-    list.push(0);
+    list.push(2);
     list.push(1);
+    list.push(0);
     validate!(v);
 
     let a = list.head.borrow_inner().opt_node_ref().unwrap();
     let b = a.borrow_inner().next.borrow_inner().opt_node_ref().unwrap();
+    let c = b.borrow_inner().next.borrow_inner().opt_node_ref().unwrap();
 
+    move_after(&a, &b);
     validate!(v);
+    assert_eq!(v.contents(), vec![Some(1), Some(0), Some(2)]);
 
-    swap_places(&a, &b);
+    move_after(&b, &c);
+    validate!(v);
+    assert_eq!(v.contents(), vec![Some(0), Some(2), Some(1)]);
 
+    move_after(&a, &b);
     validate!(v);
+    assert_eq!(v.contents(), vec![Some(2), Some(1), Some(0)]);
+}
+
+#[test]
+fn node_size() {
+    // The previous layout, where a `None` value marked a dummy node
+    #[allow(dead_code)]
+    struct OptionNode<T> {
+        next: UnsafeCell<Option<Box<OptionNode<T>>>>,
+        owning_link: *mut u8,
+        val: Option<T>,
+    }
+
+    let ptr = mem::size_of::<usize>();
+
+    // Links are non-null pointers in both layouts
+    assert_eq!(mem::size_of::<Option<Ref<u8>>>(), ptr);
+    assert_eq!(mem::size_of::<UnsafeCell<Option<Box<OptionNode<u8>>>>>(), ptr);
+
+    // A `Node<u8>` does not get smaller: the discriminant of `Option<u8>` fit
+    // into the padding after the value, and so does the slot index of a
+    // `Node`, which pads both layouts to three pointers
+    assert_eq!(mem::size_of::<OptionNode<u8>>(), 3 * ptr);
+    assert_eq!(mem::size_of::<Node<u8, Global>>(), 3 * ptr);
+    assert_eq!(mem::size_of::<Node<u32, Global>>(), 3 * ptr);
+
+    // The slot index takes the place of the discriminant, so real nodes are
+    // no smaller than in the previous layout
+    assert_eq!(mem::size_of::<Node<u64, Global>>(), 2 * ptr + 16);
+    assert_eq!(mem::size_of::<Node<u64, Global>>(), mem::size_of::<OptionNode<u64>>());
+    assert_eq!(mem::size_of::<Node<[u8; 8], Global>>(),
+               mem::size_of::<OptionNode<[u8; 8]>>());
+
+    // Dummy nodes do not store a value at all
+    assert_eq!(mem::size_of::<Header<u8, Global>>(), 2 * ptr);
+    assert!(mem::size_of::<Header<u8, Global>>() < mem::size_of::<OptionNode<u8>>());
+    assert_eq!(mem::size_of::<Header<[u64; 16], Global>>(), 2 * ptr);
 }
 
 #[test]
@@ -91,7 +212,7 @@ fn fill_and_drop() {
 
     validate!(v);
 
-    for i in 0u64..2048 {
+    for i in 0u64..2 * N {
         list.push(i);
         validate!(v);
     }
@@ -106,7 +227,7 @@ fn fill_and_iter() {
 
     validate!(v);
 
-    for i in 0u64..2048 {
+    for i in 0u64..2 * N {
         list.push(i);
         validate!(v);
     }
@@ -114,8 +235,8 @@ fn fill_and_iter() {
     let mut cursor = list.cursor();
     validate!(v);
 
-    for i in 0u64..2048 {
-        let i = 2047 - i;
+    for i in 0u64..2 * N {
+        let i = 2 * N - 1 - i;
 
         assert_eq!(cursor.next().map(|i| *i), Some(i));
         validate!(v);
@@ -130,7 +251,7 @@ fn iter_insert() {
     let mut list = TailList::new();
     let v = Validator::new(&list);
 
-    for i in 0u64..1024 {
+    for i in 0u64..N {
         list.push(i);
     }
 
@@ -139,8 +260,8 @@ fn iter_insert() {
     {
         let mut cursor = list.cursor();
 
-        for i in 0u64..1024 {
-            let i = 1023 - i;
+        for i in 0u64..N {
+            let i = N - 1 - i;
 
             cursor.next().unwrap().insert_before(i);
             validate!(v);
@@ -151,8 +272,8 @@ fn iter_insert() {
 
     let mut cursor = list.cursor();
 
-    for i in 0u64..1024 {
-        let i = 1023 - i;
+    for i in 0u64..N {
+        let i = N - 1 - i;
 
         assert_eq!(cursor.next().map(|i| *i), Some(i));
         assert_eq!(cursor.next().map(|i| *i), Some(i));
@@ -167,28 +288,28 @@ fn iter_tail() {
     let mut list = TailList::new();
     let v = Validator::new(&list);
 
-    for i in 0u64..512 {
+    for i in 0u64..N / 2 {
         list.push(i);
     }
     validate!(v);
 
     let mut cursor = list.cursor();
 
-    for i in 0u64..512 {
+    for i in 0u64..N / 2 {
         let mut next = cursor.next().unwrap();
         let (next, mut tail) = next.tail();
         validate!(v);
 
-        assert_eq!(**next, 511 - i);
+        assert_eq!(**next, N / 2 - 1 - i);
 
-        for j in i + 1 .. 512 {
-            assert_eq!(tail.next().map(|j| *j), Some(511 - j));
+        for j in i + 1 .. N / 2 {
+            assert_eq!(tail.next().map(|j| *j), Some(N / 2 - 1 - j));
             validate!(v);
         }
 
         assert!(tail.next().is_none());
 
-        assert_eq!(**next, 511 - i);
+        assert_eq!(**next, N / 2 - 1 - i);
         validate!(v);
     }
 }
@@ -198,28 +319,28 @@ fn iter_into_tail() {
     let mut list = TailList::new();
     let v = Validator::new(&list);
 
-    for i in 0u64..512 {
+    for i in 0u64..N / 2 {
         list.push(i);
     }
     validate!(v);
 
     let mut cursor = list.cursor();
 
-    for i in 0u64..512 {
+    for i in 0u64..N / 2 {
         let next = cursor.next().unwrap();
         let (next, mut tail) = next.into_tail();
         validate!(v);
 
-        assert_eq!(*next, 511 - i);
+        assert_eq!(*next, N / 2 - 1 - i);
 
-        for j in i + 1 .. 512 {
-            assert_eq!(tail.next().map(|j| *j), Some(511 - j));
+        for j in i + 1 .. N / 2 {
+            assert_eq!(tail.next().map(|j| *j), Some(N / 2 - 1 - j));
             validate!(v);
         }
 
         assert!(tail.next().is_none());
 
-        assert_eq!(*next, 511 - i);
+        assert_eq!(*next, N / 2 - 1 - i);
         validate!(v);
     }
 }
@@ -229,15 +350,15 @@ fn remove_all_iter() {
     let mut list = TailList::new();
     let v = Validator::new(&list);
 
-    for i in 0u64..1024 {
+    for i in 0u64..N {
         list.push(i);
     }
 
     {
         let mut cursor = list.cursor();
 
-        for i in 0u64..1024 {
-            let i = 1023 - i;
+        for i in 0u64..N {
+            let i = N - 1 - i;
 
             assert_eq!(cursor.next().unwrap().remove(), i);
             validate!(v);
@@ -257,16 +378,16 @@ fn mark_all() {
     let v = Validator::new(&list);
 
     {
-        let mut vec = Vec::with_capacity(1024);
+        let mut vec = Vec::with_capacity(N as usize);
 
-        for i in 0u64..1024 {
+        for i in 0u64..N {
             list.push(i);
         }
 
         {
             let mut cursor = list.cursor();
 
-            for _ in 0u64..1024 {
+            for _ in 0u64..N {
                 vec.push(cursor.next().unwrap().into_passive());
                 validate!(v);
             }
@@ -274,8 +395,8 @@ fn mark_all() {
             assert!(cursor.next().is_none());
         }
 
-        for i in 0u64..1024 {
-            assert_eq!(vec.remove((1023 - i) as usize).remove(), i);
+        for i in 0u64..N {
+            assert_eq!(vec.remove((N - 1 - i) as usize).remove(), i);
             validate!(v);
         }
     }
@@ -288,19 +409,19 @@ fn mark_all() {
 fn remove_mark_alternate() {
     let mut list = TailList::new();
     let v = Validator::new(&list);
-    let mut vec = Vec::with_capacity(512);
+    let mut vec = Vec::with_capacity(N as usize / 2);
 
-    for i in 0u64..1024 {
+    for i in 0u64..N {
         list.push(i);
     }
 
     {
         let mut cursor = list.cursor();
 
-        for i in 0u64..1024 {
-            let i = 1023 - i;
+        for i in 0u64..N {
+            let i = N - 1 - i;
 
-            if i % 2 == 0 {
+            if i.is_multiple_of(2) {
                 vec.push(cursor.next().unwrap().into_passive());
             } else {
                 assert_eq!(cursor.next().unwrap().remove(), i);
@@ -309,8 +430,8 @@ fn remove_mark_alternate() {
         }
     }
 
-    for i in 0u64..512 {
-        assert_eq!(vec.remove((511 - i) as usize).remove(), 2 * i);
+    for i in 0u64..N / 2 {
+        assert_eq!(vec.remove((N / 2 - 1 - i) as usize).remove(), 2 * i);
         validate!(v);
     }
 }
@@ -336,3 +457,1090 @@ fn remove_tail() {
     assert!(cursor.next().is_none());
     validate!(v);
 }
+
+#[test]
+fn relink_while_borrowed() {
+    let mut list = TailList::new();
+    let v = Validator::new(&list);
+
+    for i in 0u64..4 {
+        list.push(i);
+    }
+
+    {
+        let mut cursor = list.cursor();
+        let mut a = cursor.next().unwrap().into_passive();
+        let mut b = cursor.next().unwrap().into_passive();
+
+        // Keep a mutable borrow of the value of `a` alive, while its links are
+        // modified through `b`
+        let val: &mut u64 = &mut a;
+
+        b.insert_before(10);
+        b.insert_after(20).remove();
+        assert_eq!(b.remove(), 2);
+        *val += 100;
+        validate!(v);
+
+        assert_eq!(*a, 103);
+        assert_eq!(a.insert_after(30).remove(), 30);
+        validate!(v);
+    }
+
+    let mut cursor = list.cursor();
+    let vals: Vec<u64> = (0..4).map(|_| *cursor.next().unwrap()).collect();
+    assert_eq!(vals, [103, 10, 1, 0]);
+    assert!(cursor.next().is_none());
+}
+
+#[test]
+fn snapshot_restore() {
+    use super::Snapshot;
+
+    let mut list = TailList::new();
+
+    for i in 0u64..256 {
+        list.push(i);
+    }
+
+    // Save the ids of some elements the caller holds handles to
+    let mut saved = Vec::new();
+
+    {
+        let mut cursor = list.cursor();
+
+        while let Some(val_ref) = cursor.next() {
+            if *val_ref % 64 == 0 {
+                let val_ref = val_ref.into_passive();
+                saved.push((val_ref.handle().id(), *val_ref));
+            }
+        }
+    }
+
+    // Ids are kept while the list is modified, and equal values get distinct
+    // ids
+    list.push(0);
+    list.cursor().next().unwrap().insert_after(1000);
+    let removed = list.cursor().next().unwrap().into_passive().handle();
+    assert_eq!(list.try_remove(removed), Ok(0));
+
+    let entries = list.snapshot().into_entries();
+    assert_eq!(entries.len(), 257);
+    assert_eq!(entries[0].1, 1000);
+    assert_eq!(entries[1].1, 255);
+    assert!(!entries.iter().any(|&(id, _)| id == removed.id()));
+
+    for &(id, val) in &saved {
+        assert!(entries.contains(&(id, val)));
+    }
+
+    // Only elements with handles keep their ids in the next snapshot, the
+    // others are not given slots
+    let again = list.snapshot().into_entries();
+    assert_eq!(list.shared.slots.len(), saved.len() + 1);
+    assert!(again.iter().map(|e| e.1).eq(entries.iter().map(|e| e.1)));
+
+    for (&(id, val), &(old_id, _)) in again.iter().zip(&entries) {
+        assert_eq!(id == old_id, saved.contains(&(id, val)));
+        assert!(!entries.iter().any(|e| e.0 == id) || id == old_id);
+    }
+
+    let (mut restored, mut handles) = Snapshot::from_entries(entries.clone()).unwrap().restore();
+    let v = Validator::new(&restored);
+    validate!(v);
+    assert_eq!(handles.len(), 257);
+
+    for &(id, val) in &saved {
+        assert_eq!(restored.try_get(handles[&id]).map(|v| *v), Ok(val));
+    }
+
+    // The ids round-trip, and new elements get new ids
+    assert!(restored.snapshot().into_entries() == entries);
+
+    restored.push(2000);
+    let handle = restored.cursor().next().unwrap().into_passive().handle();
+    assert!(entries.iter().all(|&(id, _)| id < handle.id()));
+
+    let (id, val) = saved[1];
+    assert_eq!(restored.try_get(handles.remove(&id).unwrap()).unwrap().remove(), val);
+    validate!(v);
+
+    *restored.try_get(handles[&saved[3].0]).unwrap() += 1000;
+
+    let mut cursor = restored.cursor();
+    assert_eq!(cursor.next().map(|i| *i), Some(2000));
+    assert_eq!(cursor.next().map(|i| *i), Some(1000));
+
+    for i in 0u64..256 {
+        let i = 255 - i;
+
+        if i == val {
+            continue;
+        }
+
+        let expected = if i == 0 { 1000 } else { i };
+        assert_eq!(cursor.next().map(|i| *i), Some(expected));
+    }
+
+    assert!(cursor.next().is_none());
+    drop(cursor);
+    validate!(v);
+
+    // Persisted entries may contain an id only once
+    let entries = vec![(0, 0), (1, 1), (0, 2)];
+    assert_eq!(Snapshot::from_entries(entries).err(), Some(Error::DuplicateId));
+}
+
+#[test]
+fn custom_alloc() {
+    let live = Rc::new(Cell::new(0));
+    let mut list = TailList::new_in(CountingAlloc(live.clone()));
+    let v = Validator::new(&list);
+
+    // The head link is allocated from the list's allocator, too
+    assert_eq!(live.get(), 1);
+
+    for i in 0u64..16 {
+        list.push(i);
+    }
+    assert_eq!(live.get(), 17);
+
+    {
+        let mut cursor = list.cursor();
+        assert_eq!(live.get(), 18);
+
+        let mut next = cursor.next().unwrap();
+        next.insert_after(100);
+        next.insert_before(200);
+        assert_eq!(live.get(), 20);
+
+        let (_, mut tail) = next.tail();
+        assert_eq!(live.get(), 21);
+
+        assert_eq!(tail.next().unwrap().remove(), 100);
+        validate!(v);
+        assert_eq!(live.get(), 20);
+    }
+
+    assert_eq!(live.get(), 18);
+    validate!(v);
+
+    drop(list);
+    assert_eq!(live.get(), 0);
+}
+
+#[test]
+fn try_accessors() {
+    let mut list = TailList::new();
+    let v = Validator::new(&list);
+
+    list.push(2);
+
+    let handle = list.cursor().next().unwrap().into_passive().handle();
+
+    list.try_insert_before(handle, 1).unwrap();
+    assert_eq!(*list.try_insert_after(handle, 3).unwrap(), 3);
+    validate!(v);
+    assert_eq!(v.contents(), vec![Some(1), Some(2), Some(3)]);
+
+    assert_eq!(list.try_remove(handle), Ok(2));
+    validate!(v);
+    assert_eq!(v.contents(), vec![Some(1), Some(3)]);
+
+    assert_eq!(list.try_remove(handle), Err(Error::Stale));
+    assert!(list.try_insert_before(handle, 0).is_err());
+    assert!(list.try_insert_after(handle, 0).is_err());
+    validate!(v);
+    assert_eq!(v.contents(), vec![Some(1), Some(3)]);
+
+    {
+        let mut cursor = list.cursor();
+        let mut val = cursor.next().unwrap().into_passive();
+
+        assert_eq!(val.try_get(), Ok(&1));
+        *val.try_get_mut().unwrap() += 10;
+        val.try_insert_before(0).unwrap();
+        assert_eq!(*val.try_insert_after(2).unwrap(), 2);
+        assert_eq!(val.try_remove(), Ok(11));
+    }
+
+    validate!(v);
+    assert_eq!(v.contents(), vec![Some(0), Some(2), Some(3)]);
+
+    // A `ValRef` to a dummy node, which only exists if the list is corrupted,
+    // does not touch the node
+    {
+        let cursor = list.cursor();
+        let mut dummy = ValRef::new(cursor.dummy.clone(), cursor.shared.clone());
+
+        assert_eq!(dummy.try_get().err(), Some(Error::Dummy));
+        assert_eq!(dummy.try_get_mut().err(), Some(Error::Dummy));
+        assert_eq!(dummy.try_insert_before(0).err(), Some(Error::Dummy));
+        assert_eq!(dummy.try_insert_after(0).err(), Some(Error::Dummy));
+        assert_eq!(dummy.try_remove().err(), Some(Error::Dummy));
+    }
+
+    validate!(v);
+    assert_eq!(v.contents(), vec![Some(0), Some(2), Some(3)]);
+}
+
+#[test]
+fn try_insert_alloc_failure() {
+    // The head, the node, the dummy node of the cursor and the table of slots
+    let allocs = Rc::new(Cell::new(4));
+    let mut list = TailList::new_in(LimitedAlloc(allocs.clone()));
+    let v = Validator::new(&list);
+
+    list.push(0);
+    let handle = list.cursor().next().unwrap().into_passive().handle();
+    assert_eq!(allocs.get(), 0);
+
+    assert_eq!(list.try_insert_before(handle, 1).err(), Some(Error::Alloc));
+    assert_eq!(list.try_insert_after(handle, 1).err(), Some(Error::Alloc));
+    validate!(v);
+    assert_eq!(v.contents(), vec![Some(0)]);
+
+    allocs.set(1);
+    assert_eq!(*list.try_insert_after(handle, 1).unwrap(), 1);
+    validate!(v);
+    assert_eq!(v.contents(), vec![Some(0), Some(1)]);
+}
+
+#[test]
+fn handle_reused_memory() {
+    let mut list = TailList::new();
+
+    for i in 0..4 {
+        list.push(i);
+    }
+
+    let handle = list.cursor().next().unwrap().into_passive().handle();
+    assert_eq!(list.try_get(handle).unwrap().remove(), 3);
+
+    // The new node likely reuses the memory of the removed one, but it is a
+    // different element
+    list.push(100);
+    assert_eq!(list.try_get(handle).err(), Some(Error::Stale));
+
+    // A detached node gets a new id when it is inserted again
+    let handle = list.cursor().next().unwrap().into_passive().handle();
+    let node = list.try_get(handle).unwrap().detach();
+    list.push_node(node);
+    assert_eq!(list.try_get(handle).err(), Some(Error::Stale));
+
+    // The handle of a dropped list does not resolve in a new list, whose first
+    // element with a handle gets the same id
+    let mut old = TailList::new();
+    old.push(0);
+    let handle = old.cursor().next().unwrap().into_passive().handle();
+    drop(old);
+
+    let mut new = TailList::new();
+    new.push(1);
+    new.cursor().next().unwrap().into_passive().handle();
+    assert_eq!(new.try_get(handle).err(), Some(Error::Stale));
+
+    // The slot of a removed element is reused by the next element which gets a
+    // handle, but with a new id
+    let old = new.cursor().next().unwrap().into_passive().handle();
+    assert_eq!(new.try_remove(old), Ok(1));
+    new.push(2);
+    let handle = new.cursor().next().unwrap().into_passive().handle();
+    assert_eq!(handle.slot, old.slot);
+    assert_eq!(new.shared.slots.len(), 1);
+    assert_eq!(new.try_get(old).err(), Some(Error::Stale));
+    assert_eq!(new.try_get(handle).map(|v| *v), Ok(2));
+}
+
+#[test]
+fn handles() {
+    let mut list = TailList::new();
+    let v = Validator::new(&list);
+
+    for i in 0..N {
+        list.push(i);
+    }
+
+    // Create a handle to every element, removing every other element
+    let mut handles = Vec::new();
+
+    {
+        let mut cursor = list.cursor();
+
+        while let Some(val_ref) = cursor.next() {
+            let val_ref = val_ref.into_passive();
+            let val = *val_ref;
+            handles.push((val_ref.handle(), val, val % 2 == 1));
+
+            // A second handle to the same element is equal to the first one
+            assert!(val_ref.handle() == handles.last().unwrap().0);
+
+            if val % 2 == 1 {
+                val_ref.remove();
+            }
+        }
+    }
+
+    // Push new elements, which likely reuse the memory of the removed ones
+    for i in N..N + N / 2 {
+        list.push(i);
+    }
+
+    validate!(v);
+
+    for &(handle, val, removed) in &handles {
+        match list.try_get(handle) {
+            Ok(mut val_ref) => {
+                assert!(!removed);
+                assert_eq!(*val_ref, val);
+                val_ref.insert_after(val + 2 * N);
+            }
+            Err(err) => {
+                assert!(removed);
+                assert_eq!(err, Error::Stale);
+            }
+        }
+
+        validate!(v);
+    }
+
+    // Handles remain valid when the list is moved, but not for other lists
+    let (handle, val, _) = *handles.iter().find(|h| !h.2).unwrap();
+    let mut list = list;
+    assert_eq!(TailList::new().try_get(handle).err(), Some(Error::Stale));
+    assert_eq!(list.try_get(handle).map(|v| *v), Ok(val));
+    assert_eq!(list.try_get(handle).unwrap().remove(), val);
+    assert_eq!(list.try_get(handle).err(), Some(Error::Stale));
+}
+
+/// Returns a `Marker` after the first `n` elements of `list`.
+fn marker_after<T, A: Allocator + Clone>(list: &mut TailList<T, A>, n: usize) -> Marker<T, A> {
+    let mut cursor = list.cursor();
+
+    for _ in 0..n {
+        cursor.next().unwrap();
+    }
+
+    cursor.into_marker()
+}
+
+#[test]
+fn resume_marker() {
+    let mut list = TailList::new();
+
+    for i in (0..N).rev() {
+        list.push(i);
+    }
+
+    let marker = marker_after(&mut list, N as usize / 2);
+
+    // The list can be used and moved while the marker exists
+    list.push(N);
+    let mut other = marker_after(&mut list, 3);
+    let mut list = list;
+    let v = Validator::new(&list);
+
+    {
+        let mut cursor = list.cursor();
+        assert_eq!(cursor.next().map(|v| *v), Some(N));
+
+        // Remove the elements directly before and after the marker
+        while let Some(val) = cursor.next() {
+            if *val == N / 2 - 1 || *val == N / 2 {
+                val.remove();
+            }
+        }
+    }
+
+    validate!(v);
+
+    // N, 0, 1, <other>, 2, ..., N / 2 - 2, <marker>, N / 2 + 1, ...
+    let mut expected: Vec<_> = (N / 2 + 1..N).map(Some).collect();
+    expected.insert(0, None);
+    assert_eq!(v.contents()[N as usize / 2 + 1..], expected[..]);
+
+    {
+        let mut cursor = list.resume(marker);
+
+        for i in N / 2 + 1..N {
+            assert_eq!(cursor.next().map(|v| *v), Some(i));
+        }
+
+        assert!(cursor.next().is_none());
+    }
+
+    // A resumed cursor may be turned into a marker again
+    other = {
+        let mut cursor = list.resume(other);
+        assert_eq!(cursor.next().map(|v| *v), Some(2));
+        cursor.into_marker()
+    };
+
+    validate!(v);
+    assert_eq!(v.contents()[..5], [Some(N), Some(0), Some(1), Some(2), None]);
+
+    drop(other);
+    validate!(v);
+}
+
+#[test]
+fn drop_marker() {
+    let allocs = Rc::new(Cell::new(0));
+
+    // The list is dropped first
+    {
+        let mut list = TailList::new_in(CountingAlloc(allocs.clone()));
+        list.push(0);
+        let marker = marker_after(&mut list, 1);
+        drop(list);
+
+        assert_eq!(allocs.get(), 1);
+        drop(marker);
+        assert_eq!(allocs.get(), 0);
+    }
+
+    // The marker is dropped first
+    {
+        let mut list = TailList::new_in(CountingAlloc(allocs.clone()));
+        let v = Validator::new(&list);
+
+        for i in 0..4 {
+            list.push(i);
+        }
+
+        // The dummy nodes are removed right away
+        drop(marker_after(&mut list, 2));
+        drop(marker_after(&mut list, 2));
+        assert_eq!(allocs.get(), 1 + 4);
+        validate!(v);
+        assert_eq!(v.contents(), [Some(3), Some(2), Some(1), Some(0)]);
+
+        // Also after the list has been moved
+        let marker = marker_after(&mut list, 3);
+        let mut list = list;
+        drop(marker);
+        assert_eq!(allocs.get(), 1 + 4);
+        validate!(v);
+        assert_eq!(v.contents(), [Some(3), Some(2), Some(1), Some(0)]);
+
+        // Read cursors never stop at a dummy node, which may be freed while
+        // they are in use
+        let marker = marker_after(&mut list, 1);
+        let mut read_cursor = list.read_cursor();
+        assert_eq!(read_cursor.next(), Some(&3));
+        drop(marker);
+        assert!(read_cursor.eq([2, 1, 0].iter()));
+    }
+
+    assert_eq!(allocs.get(), 0);
+}
+
+#[test]
+#[should_panic(expected = "marker does not belong to this list")]
+fn resume_foreign_marker() {
+    let mut list = TailList::new();
+    let mut other = TailList::new();
+
+    list.push(0);
+    other.push(0);
+
+    let marker = marker_after(&mut list, 1);
+    other.resume(marker);
+}
+
+#[test]
+fn read_cursors() {
+    let mut list = TailList::new();
+
+    for i in (0..N).rev() {
+        list.push(i);
+    }
+
+    // Dummy nodes of leaked cursors and markers are skipped
+    let marker = marker_after(&mut list, 3);
+    {
+        let mut cursor = list.cursor();
+        cursor.next();
+        mem::forget(cursor);
+    }
+
+    let mut a = list.read_cursor();
+    let mut b = list.read_cursor();
+
+    for i in 0..N / 2 {
+        assert_eq!(a.next(), Some(&i));
+    }
+
+    let c = a.clone();
+
+    for i in 0..N {
+        assert_eq!(b.next(), Some(&i));
+    }
+
+    assert!(b.next().is_none());
+    assert!(a.cloned().eq(N / 2..N));
+    assert!(c.cloned().eq(N / 2..N));
+    assert!(list.read_cursor().cloned().eq(0..N));
+
+    // The dummy nodes are still there
+    assert_eq!(Validator::new(&list).contents().len(), N as usize + 2);
+    drop(marker);
+}
+
+#[test]
+fn next_pair() {
+    let mut list = TailList::new();
+    let v = Validator::new(&list);
+
+    for i in (0..N).rev() {
+        list.push(i);
+    }
+
+    // A marker between the elements of a pair is skipped
+    let marker = marker_after(&mut list, 1);
+
+    {
+        let mut cursor = list.cursor();
+        let mut expected = 0;
+
+        // Merge every odd element into its predecessor
+        while let Some((mut a, b)) = cursor.next_pair() {
+            assert_eq!((*a, *b), (expected, expected + 1));
+            *a += b.remove();
+            expected += 2;
+        }
+
+        // The last element is not consumed
+        assert_eq!(expected, N);
+        assert!(cursor.next().is_none());
+    }
+
+    validate!(v);
+
+    {
+        let mut cursor = list.cursor();
+        let mut expected = 1;
+
+        while let Some((a, b)) = cursor.next_pair() {
+            assert_eq!((*a, *b), (expected, expected + 4));
+            expected += 4;
+        }
+
+        assert_eq!(cursor.next().map(|v| *v), Some(2 * N - 3));
+        assert!(cursor.next().is_none());
+    }
+
+    drop(marker);
+    validate!(v);
+}
+
+#[test]
+fn next_window() {
+    let mut list = TailList::new();
+    let v = Validator::new(&list);
+
+    for i in (0..N).rev() {
+        list.push(i);
+    }
+
+    {
+        let mut cursor = list.cursor();
+
+        for i in 0..N - 2 {
+            let [a, b, c] = cursor.next_window().unwrap();
+            assert_eq!([*a, *b, *c], [i, i + 1, i + 2]);
+        }
+
+        assert!(cursor.next_window::<3>().is_none());
+        assert_eq!(cursor.next().map(|v| *v), Some(N - 2));
+        assert_eq!(cursor.next_window().map(|[w]| *w), Some(N - 1));
+        assert!(cursor.next_window::<1>().is_none());
+    }
+
+    validate!(v);
+
+    {
+        let mut cursor = list.cursor();
+
+        // Remove the middle element of every window
+        while let Some([_, middle, _]) = cursor.next_window() {
+            middle.remove();
+        }
+    }
+
+    validate!(v);
+    assert!(list.read_cursor().cloned().eq((0..N).filter(|i| i % 2 == 0)
+                                                .chain(Some(N - 1))));
+}
+
+#[test]
+fn coalesce() {
+    let allocs = Rc::new(Cell::new(0));
+    let mut list = TailList::new_in(CountingAlloc(allocs.clone()));
+    let v = Validator::new(&list);
+
+    // Runs of equal values, as (value, count) pairs
+    for i in (0..N).rev() {
+        list.push((i / 4, 1));
+    }
+
+    // Markers within and between runs are skipped
+    let a = marker_after(&mut list, 1);
+    let b = marker_after(&mut list, 4);
+    assert_eq!(allocs.get(), 1 + N as usize + 2);
+
+    // Only coalesce the runs after the first one
+    {
+        let mut cursor = list.cursor();
+
+        for _ in 0..4 {
+            cursor.next();
+        }
+
+        cursor.coalesce(|a, b| {
+            assert!(a.0 <= b.0);
+
+            if a.0 == b.0 {
+                a.1 += b.1;
+                return true;
+            }
+
+            false
+        });
+
+        assert!(cursor.next().is_none());
+    }
+
+    validate!(v);
+    assert_eq!(allocs.get(), 1 + 4 + N as usize / 4 - 1 + 2);
+    assert!(list.read_cursor().cloned().eq((0..4).map(|_| (0, 1))
+                                           .chain((1..N / 4).map(|i| (i, 4)))));
+
+    // Merge everything into the first element
+    list.coalesce(|a, b| {
+        a.1 += b.1;
+        true
+    });
+
+    validate!(v);
+    assert_eq!(allocs.get(), 1 + 1 + 2);
+    assert!(list.read_cursor().cloned().eq(Some((0, N))));
+
+    drop((a, b));
+    drop(list);
+    assert_eq!(allocs.get(), 0);
+}
+
+#[test]
+fn skip_many_dummies() {
+    // Enough dummy nodes to overflow the stack if they were skipped recursively
+    let m = if cfg!(miri) { 64 } else { 100_000 };
+
+    let mut list = TailList::new();
+    let v = Validator::new(&list);
+
+    list.push(1);
+    list.push(0);
+
+    {
+        let mut cursor = list.cursor();
+        let mut first = cursor.next().unwrap();
+
+        // Pile up the dummy nodes of leaked nested cursors after the first
+        // element
+        for _ in 0..m {
+            let (_, tail) = first.tail();
+            mem::forget(tail);
+        }
+
+        let (_, mut tail) = first.tail();
+        assert_eq!(tail.next().map(|v| *v), Some(1));
+        assert!(tail.next().is_none());
+    }
+
+    validate!(v);
+
+    // Pile up the dummy nodes of leaked cursors at the front of the list
+    for _ in 0..m {
+        mem::forget(list.cursor());
+    }
+
+    let mut cursor = list.cursor();
+    assert_eq!(cursor.next().map(|v| *v), Some(0));
+    assert_eq!(cursor.next().map(|v| *v), Some(1));
+    assert!(cursor.next().is_none());
+    drop(cursor);
+
+    validate!(v);
+    assert_eq!(v.contents().len(), 2 * m + 2);
+}
+
+#[test]
+fn purge_dummies() {
+    let allocs = Rc::new(Cell::new(0));
+    let mut list = TailList::new_in(CountingAlloc(allocs.clone()));
+
+    for i in (0..N).rev() {
+        list.push(i);
+    }
+
+    // Leak a cursor after every element, keep a marker and drop another one
+    for i in 0..N as usize {
+        let mut cursor = list.cursor();
+
+        for _ in 0..i {
+            cursor.next();
+        }
+
+        mem::forget(cursor);
+    }
+
+    let marker = marker_after(&mut list, 5);
+    drop(marker_after(&mut list, 10));
+
+    assert_eq!(list.dummy_count(), N as usize + 1);
+    assert_eq!(allocs.get(), 1 + 2 * N as usize + 1);
+    assert!(list.read_cursor().cloned().eq(0..N));
+
+    // Cursors skip the dummy nodes of leaked cursors
+    {
+        let mut cursor = list.cursor();
+        while cursor.next().is_some() {}
+    }
+
+    assert_eq!(list.dummy_count(), N as usize + 1);
+
+    // Purging works after the list has been moved
+    let mut list = list;
+    let v = Validator::new(&list);
+
+    assert_eq!(list.purge_dummies(), N as usize);
+    validate!(v);
+    assert_eq!(list.dummy_count(), 1);
+    assert_eq!(allocs.get(), 1 + N as usize + 1);
+    assert_eq!(list.purge_dummies(), 0);
+
+    // The marker is still usable
+    {
+        let mut cursor = list.resume(marker);
+        assert_eq!(cursor.next().map(|v| *v), Some(5));
+    }
+
+    assert_eq!(list.dummy_count(), 0);
+    drop(list);
+    assert_eq!(allocs.get(), 0);
+}
+
+#[test]
+fn detach_and_insert_nodes() {
+    let allocs = Rc::new(Cell::new(0));
+    let mut a = TailList::new_in(CountingAlloc(allocs.clone()));
+    let mut b = TailList::new_in(CountingAlloc(allocs.clone()));
+    let va = Validator::new(&a);
+    let vb = Validator::new(&b);
+
+    for i in (0..N).rev() {
+        a.push(i);
+    }
+
+    assert_eq!(allocs.get(), 2 + N as usize);
+
+    // Move every odd element to `b`, without allocating
+    {
+        let mut cursor = a.cursor();
+
+        while let Some(val) = cursor.next() {
+            if *val % 2 == 1 {
+                let mut node = val.detach();
+                *node += N;
+                b.push_node(node);
+            }
+        }
+    }
+
+    validate!(va);
+    validate!(vb);
+    assert_eq!(allocs.get(), 2 + N as usize);
+    assert!(a.read_cursor().cloned().eq((0..N).step_by(2)));
+    assert!(b.read_cursor().cloned().eq((0..N).rev().skip_while(|i| i % 2 == 0)
+                                            .step_by(2).map(|i| i + N)));
+
+    // Move all elements of `b` back after their original predecessors
+    let nodes: Vec<DetachedNode<_, _>> = {
+        let mut cursor = b.cursor();
+        let mut nodes = Vec::new();
+
+        while let Some(val) = cursor.next() {
+            nodes.push(val.detach());
+        }
+
+        nodes
+    };
+
+    assert_eq!(b.read_cursor().count(), 0);
+    assert_eq!(allocs.get(), 2 + N as usize);
+
+    {
+        let mut cursor = a.cursor();
+        let mut nodes = nodes.into_iter().rev();
+
+        while let Some(mut val) = cursor.next() {
+            let mut node = nodes.next().unwrap();
+            assert_eq!(*node, *val + 1 + N);
+            *node -= N;
+
+            if *val % 4 == 0 {
+                val.insert_node_after(node);
+            } else {
+                // Insert the node behind the cursor and put the predecessor
+                // behind it
+                let pred = val.detach();
+                cursor.insert_node(node).insert_node_before(pred);
+            }
+        }
+    }
+
+    validate!(va);
+    validate!(vb);
+    assert_eq!(allocs.get(), 2 + N as usize);
+    assert!(a.read_cursor().cloned().eq(0..N));
+
+    // Detached nodes can be turned back into their values or be dropped
+    {
+        let mut cursor = a.cursor();
+        let first = cursor.next().unwrap().detach();
+        let second = cursor.next().unwrap().into_passive().detach();
+        assert_eq!(first.into_inner(), 0);
+        assert_eq!(*second, 1);
+    }
+
+    validate!(va);
+    assert_eq!(allocs.get(), 2 + N as usize - 2);
+    drop(a);
+    drop(b);
+    assert_eq!(allocs.get(), 0);
+}
+
+#[test]
+fn insert_next_to_foreign_node() {
+    let a_allocs = Rc::new(Cell::new(0));
+    let b_allocs = Rc::new(Cell::new(0));
+    let mut a = TailList::new_in(CountingAlloc(a_allocs.clone()));
+    let mut b = TailList::new_in(CountingAlloc(b_allocs.clone()));
+
+    a.push(0);
+    let node = a.cursor().next().unwrap().detach();
+    b.push_node(node);
+
+    // The node keeps the allocator of `a`, but nodes inserted next to it and
+    // the dummy nodes of cursors over its tail are allocated by `b`
+    {
+        let mut cursor = b.cursor();
+        let mut val = cursor.next().unwrap();
+        val.insert_before(1);
+        val.insert_after(2);
+
+        let (_, tail) = val.tail();
+        assert_eq!(a_allocs.get(), 2);
+        assert_eq!(b_allocs.get(), 5);
+        drop(tail);
+    }
+
+    assert!(b.read_cursor().cloned().eq(vec![1, 0, 2]));
+    drop(a);
+    assert_eq!(a_allocs.get(), 1);
+    drop(b);
+    assert_eq!(a_allocs.get(), 0);
+    assert_eq!(b_allocs.get(), 0);
+}
+
+#[test]
+fn detached_handles() {
+    let mut a = TailList::new();
+    let mut b = TailList::new();
+
+    a.push(0);
+
+    let (handle, node) = {
+        let mut cursor = a.cursor();
+        let val = cursor.next().unwrap().into_passive();
+        (val.handle(), val.detach())
+    };
+
+    // Detaching an element removes it from its list, so the handle does not
+    // follow it into the other list
+    assert_eq!(a.try_get(handle).err(), Some(Error::Stale));
+    b.push_node(node);
+    assert_eq!(b.try_get(handle).err(), Some(Error::Stale));
+    assert_eq!(a.try_get(handle).err(), Some(Error::Stale));
+
+    // A new handle resolves in the other list
+    let handle = b.cursor().next().unwrap().into_passive().handle();
+    assert_eq!(b.try_get(handle).map(|v| *v), Ok(0));
+    assert_eq!(a.try_get(handle).err(), Some(Error::Stale));
+}
+
+#[test]
+fn replace_take_and_swap() {
+    let mut list = TailList::new();
+    let v = Validator::new(&list);
+
+    for i in (0..3).rev() {
+        list.push(i);
+    }
+
+    {
+        let mut cursor = list.cursor();
+        let (mut a, mut b) = cursor.next_pair().unwrap();
+        assert_eq!(a.replace(10), 0);
+        assert_eq!(b.take(), 1);
+        a.swap_values(&mut b);
+        assert_eq!((*a, *b), (0, 10));
+    }
+
+    validate!(v);
+    assert!(list.read_cursor().cloned().eq(vec![0, 10, 2]));
+}
+
+#[test]
+fn map_val_ref() {
+    let mut list = TailList::new();
+    let v = Validator::new(&list);
+
+    // (key, (hits, payload)) entries
+    for i in (0..3).rev() {
+        list.push((i, (0, i * 10)));
+    }
+
+    {
+        let mut cursor = list.cursor();
+        cursor.next();
+        let mut entry = cursor.next().unwrap().into_passive().map(|e| &mut e.1);
+        entry.0 += 1;
+        let mut payload = entry.map(|e| &mut e.1);
+        assert_eq!(*payload, 10);
+        *payload += 1;
+        assert_eq!(cursor.next().map(|v| v.1), Some((0, 20)));
+    }
+
+    validate!(v);
+    assert!(list.read_cursor().cloned().eq(vec![(0, (0, 0)), (1, (1, 11)), (2, (0, 20))]));
+}
+
+include!("./self_ref_tests.rs");
+
+#[test]
+fn pinned_elements() {
+    let drops = Rc::new(Cell::new(0));
+    let mut a = TailList::new();
+    let mut b = TailList::new();
+    let v = Validator::new(&a);
+
+    for _ in 0..N {
+        unsafe { a.push_pinned(SelfRef::new(&drops)) }.init();
+    }
+
+    // Pin the elements pushed without pinning as well
+    b.push(SelfRef::new(&drops));
+    b.push(SelfRef::new(&drops));
+    {
+        let mut cursor = b.cursor();
+
+        while let Some(val) = cursor.next() {
+            unsafe { val.into_passive().as_pin_mut() }.init();
+        }
+    }
+
+    // Moving nodes between lists and coalescing does not move the values
+    let node = {
+        let mut cursor = a.cursor();
+        cursor.next();
+        cursor.next().unwrap().into_passive().detach()
+    };
+    b.push_node(node);
+    b.coalesce(|_, _| true);
+
+    validate!(v);
+    assert_eq!(drops.get(), 2);
+
+    for val in a.read_cursor().chain(b.read_cursor()) {
+        val.check();
+    }
+
+    drop((a, b));
+    assert_eq!(drops.get(), N as usize + 2);
+}
+
+include!("./model_tests.rs");
+
+impl Check for Validator<u64> {
+    fn new(list: &TailList<u64>) -> Validator<u64> {
+        Validator::new(list)
+    }
+
+    /// Validates the list and checks that it matches the model.
+    fn check(&self, model: &Model) {
+        let v = self;
+        validate!(v);
+        assert_eq!(v.contents(), model.contents());
+    }
+
+    fn check_idle(&self, _: &mut TailList<u64>, model: &Model) {
+        self.check(model);
+    }
+}
+
+/// Returns a strategy generating arbitrary model test operations.
+fn op_strategy() -> BoxedStrategy<Op> {
+    let on_next = prop_oneof![
+        Just(OnNext::Drop),
+        Just(OnNext::InsertBefore),
+        Just(OnNext::InsertAfter),
+        Just(OnNext::Remove),
+        Just(OnNext::Detach),
+        Just(OnNext::IntoPassive),
+        Just(OnNext::Tail),
+        Just(OnNext::IntoTail),
+    ];
+
+    let on_pair = prop_oneof![
+        Just(OnPair::Drop),
+        Just(OnPair::RemoveSecond),
+        Just(OnPair::SwapValues),
+    ];
+
+    let on_passive = prop_oneof![
+        Just(OnPassive::InsertBefore),
+        Just(OnPassive::InsertAfter),
+        Just(OnPassive::Remove),
+        Just(OnPassive::Detach),
+        Just(OnPassive::Replace),
+    ];
+
+    prop_oneof![
+        6 => Just(Op::Push),
+        1 => any::<usize>().prop_map(Op::PushNode),
+        2 => Just(Op::Cursor),
+        1 => any::<usize>().prop_map(Op::Resume),
+        1 => Just(Op::Purge),
+        1 => any::<usize>().prop_map(Op::DropMarker),
+        12 => on_next.prop_map(Op::Next),
+        1 => on_pair.prop_map(Op::NextPair),
+        1 => (any::<usize>(), any::<bool>()).prop_map(|(k, r)| Op::NextWindow(k, r)),
+        1 => any::<usize>().prop_map(Op::InsertNode),
+        1 => any::<u64>().prop_map(Op::Coalesce),
+        4 => (any::<usize>(), on_passive).prop_map(|(i, p)| Op::Passive(i, p)),
+        1 => Just(Op::End),
+        1 => Just(Op::IntoMarker),
+        1 => Just(Op::Forget),
+    ].boxed()
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(if cfg!(miri) { 4 } else { 512 }))]
+
+    #[test]
+    fn model(ops in prop::collection::vec(op_strategy(), 0..128)) {
+        run_model::<Validator<u64>>(&ops);
+    }
+}
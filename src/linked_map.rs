@@ -0,0 +1,352 @@
+//! A hash map which keeps the order of its entries in a tail list.
+//!
+//! A `LinkedHashMap` stores its entries in a `TailList`, in insertion order,
+//! and maps every key to the node of its entry. An entry can thus be looked
+//! up, removed or moved to either end of the list in O(1).
+//!
+//! The keys in the `HashMap` are pointers to the keys in the nodes, which are
+//! never moved while the entry exists, so keys are not stored twice.
+//!
+//! This module requires the `std` feature.
+
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::iter::FromIterator;
+use std::mem;
+use allocator_api2::alloc::Global;
+use super::{val_ptr, Cursor, NodeRef, Queue, ReadCursor, TailValRef};
+
+////////////////////////////////////////////////////////////////////////////////
+// STRUCTS
+////////////////////////////////////////////////////////////////////////////////
+
+/// A hash map with a well-defined order of its entries (see the module
+/// documentation).
+///
+/// New entries are inserted at the back. The order can be changed with
+/// `move_to_front` and `move_to_back`.
+pub struct LinkedHashMap<K, V, S = RandomState> {
+    map: HashMap<KeyRef<K>, NodeRef<(K, V), Global>, S>,
+    entries: Queue<(K, V)>,
+}
+
+/// An iterator over the entries of a `LinkedHashMap`, in order.
+pub struct Iter<'node, K: 'node, V: 'node> {
+    entries: ReadCursor<'node, (K, V)>,
+}
+
+/// A `MapCursor` iterates over the entries of a `LinkedHashMap` in order,
+/// allowing them to be modified and removed. It is an active item, like a
+/// `Cursor`.
+pub struct MapCursor<'node, K: 'node, V: 'node, S: 'node = RandomState> {
+    cursor: Cursor<'node, (K, V)>,
+    map: &'node mut HashMap<KeyRef<K>, NodeRef<(K, V), Global>, S>,
+}
+
+/// An entry returned by a `MapCursor`.
+pub struct CursorEntry<'node, 'tail, K: 'node + 'tail, V: 'node + 'tail, S: 'tail> {
+    val: TailValRef<'node, 'tail, (K, V)>,
+    map: &'tail mut HashMap<KeyRef<K>, NodeRef<(K, V), Global>, S>,
+}
+
+/// A pointer to a key stored in a node, which hashes and compares like the
+/// key. Used by the hash maps of this module and the `cache` module, so keys
+/// are not stored twice.
+pub(crate) struct KeyRef<K>(pub(crate) *const K);
+
+/// A key which is borrowed as `Q`, used to look up a `KeyRef` by `&Q`.
+#[repr(transparent)]
+pub(crate) struct Qey<Q: ?Sized>(Q);
+
+////////////////////////////////////////////////////////////////////////////////
+// IMPLS
+////////////////////////////////////////////////////////////////////////////////
+
+impl<K: Hash + Eq, V> LinkedHashMap<K, V> {
+    /// Returns a new, empty map.
+    pub fn new() -> LinkedHashMap<K, V> {
+        LinkedHashMap::with_hasher(RandomState::new())
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> LinkedHashMap<K, V, S> {
+    /// Returns a new, empty map which uses `hash_builder` to hash its keys.
+    ///
+    /// Unlike an empty `HashMap`, this allocates: the head of the entry list
+    /// and the dummy node which marks its back.
+    pub fn with_hasher(hash_builder: S) -> LinkedHashMap<K, V, S> {
+        LinkedHashMap {
+            map: HashMap::with_hasher(hash_builder),
+            entries: Queue::new(),
+        }
+    }
+
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns whether the map contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Inserts a value for `key`. If the map already contains an entry for the
+    /// key, its value is replaced and returned, and the entry keeps its
+    /// position. Otherwise, a new entry is inserted at the back.
+    pub fn insert(&mut self, key: K, val: V) -> Option<V> {
+        if let Some(node_ref) = self.map.get(Qey::from_ref(&key)) {
+            return Some(mem::replace(unsafe { &mut (*val_ptr(node_ref)).1 }, val));
+        }
+
+        let node_ref = self.entries.push_back((key, val));
+        self.map.insert(key_ref(&node_ref), node_ref);
+        None
+    }
+
+    /// Returns a reference to the value for `key`, if any.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+        where K: Borrow<Q>, Q: ?Sized + Hash + Eq
+    {
+        self.map.get(Qey::from_ref(key)).map(|node_ref| unsafe { &(*val_ptr(node_ref)).1 })
+    }
+
+    /// Returns a mutable reference to the value for `key`, if any.
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+        where K: Borrow<Q>, Q: ?Sized + Hash + Eq
+    {
+        self.map.get(Qey::from_ref(key)).map(|node_ref| unsafe { &mut (*val_ptr(node_ref)).1 })
+    }
+
+    /// Returns whether the map contains an entry for `key`.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+        where K: Borrow<Q>, Q: ?Sized + Hash + Eq
+    {
+        self.map.contains_key(Qey::from_ref(key))
+    }
+
+    /// Removes the entry for `key` and returns its value, if any.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+        where K: Borrow<Q>, Q: ?Sized + Hash + Eq
+    {
+        self.remove_entry(key).map(|(_, val)| val)
+    }
+
+    /// Removes the entry for `key` and returns its key and value, if any.
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+        where K: Borrow<Q>, Q: ?Sized + Hash + Eq
+    {
+        let node_ref = self.map.remove(Qey::from_ref(key))?;
+
+        Some(self.entries.remove(node_ref))
+    }
+
+    /// Moves the entry for `key` to the front and returns whether there is
+    /// such an entry.
+    pub fn move_to_front<Q>(&mut self, key: &Q) -> bool
+        where K: Borrow<Q>, Q: ?Sized + Hash + Eq
+    {
+        let node_ref = match self.map.get(Qey::from_ref(key)) {
+            Some(node_ref) => node_ref.clone(),
+            None => return false,
+        };
+
+        self.entries.unlink(&node_ref);
+        self.entries.link_front(&node_ref);
+        true
+    }
+
+    /// Moves the entry for `key` to the back and returns whether there is such
+    /// an entry.
+    pub fn move_to_back<Q>(&mut self, key: &Q) -> bool
+        where K: Borrow<Q>, Q: ?Sized + Hash + Eq
+    {
+        let node_ref = match self.map.get(Qey::from_ref(key)) {
+            Some(node_ref) => node_ref.clone(),
+            None => return false,
+        };
+
+        self.entries.unlink(&node_ref);
+        self.entries.link_back(&node_ref);
+        true
+    }
+
+    /// Returns the first entry, if any.
+    pub fn front(&self) -> Option<(&K, &V)> {
+        self.entries.front().map(|node_ref| {
+            let entry = unsafe { &*val_ptr(&node_ref) };
+            (&entry.0, &entry.1)
+        })
+    }
+
+    /// Removes the first entry and returns its key and value, if any.
+    pub fn pop_front(&mut self) -> Option<(K, V)> {
+        let node_ref = self.entries.front()?;
+        self.map.remove(&key_ref(&node_ref));
+        Some(self.entries.remove(node_ref))
+    }
+
+    /// Returns an iterator over the entries, in order.
+    pub fn iter<'node>(&'node self) -> Iter<'node, K, V> {
+        Iter {
+            entries: self.entries.list.read_cursor(),
+        }
+    }
+
+    /// Returns a cursor over the entries, in order.
+    pub fn cursor<'node>(&'node mut self) -> MapCursor<'node, K, V, S> {
+        MapCursor {
+            cursor: self.entries.list.cursor(),
+            map: &mut self.map,
+        }
+    }
+}
+
+impl<'node, K: 'node + Hash + Eq, V: 'node, S: 'node + BuildHasher> MapCursor<'node, K, V, S> {
+    /// (Optionally) returns the next entry of this cursor.
+    pub fn next<'tail>(&'tail mut self) -> Option<CursorEntry<'node, 'tail, K, V, S>> {
+        let val = self.cursor.next()?;
+
+        Some(CursorEntry {
+            val,
+            map: &mut *self.map,
+        })
+    }
+}
+
+impl<'node, 'tail, K: 'node + 'tail + Hash + Eq, V: 'node + 'tail, S: 'tail + BuildHasher>
+    CursorEntry<'node, 'tail, K, V, S>
+{
+    /// Returns the key of this entry.
+    pub fn key(&self) -> &K {
+        unsafe { &(*val_ptr(&self.val.val_ref.node)).0 }
+    }
+
+    /// Returns the value of this entry.
+    pub fn get(&self) -> &V {
+        unsafe { &(*val_ptr(&self.val.val_ref.node)).1 }
+    }
+
+    /// Returns the value of this entry mutably.
+    pub fn get_mut(&mut self) -> &mut V {
+        unsafe { &mut (*val_ptr(&self.val.val_ref.node)).1 }
+    }
+
+    /// Returns the value of this entry mutably, for the `'node` lifetime.
+    pub fn into_mut(self) -> &'node mut V {
+        unsafe { &mut (*val_ptr(&self.val.val_ref.node)).1 }
+    }
+
+    /// Removes this entry from the map and returns its key and value.
+    pub fn remove(self) -> (K, V) {
+        self.map.remove(&key_ref(&self.val.val_ref.node));
+        self.val.remove()
+    }
+}
+
+impl<Q: ?Sized> Qey<Q> {
+    /// Returns `q` as a `Qey`.
+    pub(crate) fn from_ref(q: &Q) -> &Qey<Q> {
+        unsafe { &*(q as *const Q as *const Qey<Q>) }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// FUNCTIONS
+////////////////////////////////////////////////////////////////////////////////
+
+/// Returns a `KeyRef` to the key of the given node.
+fn key_ref<K, V>(node_ref: &NodeRef<(K, V), Global>) -> KeyRef<K> {
+    KeyRef(unsafe { &raw const (*val_ptr(node_ref)).0 })
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// TRAIT IMPLS
+////////////////////////////////////////////////////////////////////////////////
+
+impl<K: Hash> Hash for KeyRef<K> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        unsafe { (*self.0).hash(state) }
+    }
+}
+
+impl<K: PartialEq> PartialEq for KeyRef<K> {
+    fn eq(&self, other: &KeyRef<K>) -> bool {
+        unsafe { *self.0 == *other.0 }
+    }
+}
+
+impl<K: Eq> Eq for KeyRef<K> {}
+
+impl<K, Q: ?Sized> Borrow<Qey<Q>> for KeyRef<K> where K: Borrow<Q> {
+    fn borrow(&self) -> &Qey<Q> {
+        Qey::from_ref(unsafe { (*self.0).borrow() })
+    }
+}
+
+impl<Q: ?Sized + Hash> Hash for Qey<Q> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+impl<Q: ?Sized + PartialEq> PartialEq for Qey<Q> {
+    fn eq(&self, other: &Qey<Q>) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<Q: ?Sized + Eq> Eq for Qey<Q> {}
+
+impl<K: Hash + Eq, V, S: BuildHasher + Default> Default for LinkedHashMap<K, V, S> {
+    fn default() -> LinkedHashMap<K, V, S> {
+        LinkedHashMap::with_hasher(S::default())
+    }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher + Default> FromIterator<(K, V)> for LinkedHashMap<K, V, S> {
+    fn from_iter<I: IntoIterator<Item=(K, V)>>(iter: I) -> LinkedHashMap<K, V, S> {
+        let mut map = LinkedHashMap::default();
+
+        for (key, val) in iter {
+            map.insert(key, val);
+        }
+
+        map
+    }
+}
+
+impl<K: Hash + Eq + fmt::Debug, V: fmt::Debug, S: BuildHasher> fmt::Debug
+    for LinkedHashMap<K, V, S>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<'node, K: 'node, V: 'node> Iterator for Iter<'node, K, V> {
+    type Item = (&'node K, &'node V);
+
+    fn next(&mut self) -> Option<(&'node K, &'node V)> {
+        self.entries.next().map(|entry| (&entry.0, &entry.1))
+    }
+}
+
+impl<'node, K: 'node + Hash + Eq, V: 'node, S: BuildHasher> IntoIterator
+    for &'node LinkedHashMap<K, V, S>
+{
+    type Item = (&'node K, &'node V);
+    type IntoIter = Iter<'node, K, V>;
+
+    fn into_iter(self) -> Iter<'node, K, V> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    include!( "./linked_map_tests.rs");
+}
@@ -0,0 +1,132 @@
+use std::collections::hash_map::RandomState;
+use super::LinkedHashMap;
+
+/// Returns the keys of `map`, in order.
+fn keys<V>(map: &LinkedHashMap<u32, V>) -> Vec<u32> {
+    map.iter().map(|(&key, _)| key).collect()
+}
+
+#[test]
+fn insert_and_remove() {
+    let mut map = LinkedHashMap::new();
+    assert!(map.is_empty());
+
+    for i in 0..8 {
+        assert_eq!(map.insert(i, i * 10), None);
+    }
+
+    assert_eq!(map.len(), 8);
+    assert_eq!(keys(&map), [0, 1, 2, 3, 4, 5, 6, 7]);
+
+    // Replacing a value keeps the position of the entry
+    assert_eq!(map.insert(3, 31), Some(30));
+    assert_eq!(map.get(&3), Some(&31));
+    *map.get_mut(&4).unwrap() += 1;
+    assert_eq!(map.get(&4), Some(&41));
+    assert_eq!(keys(&map), [0, 1, 2, 3, 4, 5, 6, 7]);
+
+    assert_eq!(map.remove(&0), Some(0));
+    assert_eq!(map.remove(&0), None);
+    assert_eq!(map.remove_entry(&7), Some((7, 70)));
+    assert_eq!(map.remove(&4), Some(41));
+    assert!(!map.contains_key(&4));
+    assert!(map.contains_key(&5));
+    assert_eq!(map.len(), 5);
+    assert_eq!(keys(&map), [1, 2, 3, 5, 6]);
+
+    // Removed entries can be inserted again, at the back
+    map.insert(0, 0);
+    assert_eq!(keys(&map), [1, 2, 3, 5, 6, 0]);
+}
+
+#[test]
+fn move_entries() {
+    let mut map: LinkedHashMap<_, _> = (0..6).map(|i| (i, ())).collect();
+
+    assert!(map.move_to_back(&0));
+    assert!(map.move_to_back(&3));
+    assert!(map.move_to_back(&3));
+    assert!(map.move_to_front(&5));
+    assert!(map.move_to_front(&5));
+    assert!(!map.move_to_front(&6));
+    assert!(!map.move_to_back(&6));
+    assert_eq!(keys(&map), [5, 1, 2, 4, 0, 3]);
+
+    assert_eq!(map.front(), Some((&5, &())));
+    assert_eq!(map.pop_front(), Some((5, ())));
+    assert_eq!(map.pop_front(), Some((1, ())));
+    assert!(!map.contains_key(&1));
+    assert_eq!(keys(&map), [2, 4, 0, 3]);
+
+    while map.pop_front().is_some() {}
+    assert!(map.is_empty());
+    assert_eq!(map.front(), None);
+
+    map.insert(1, ());
+    assert!(map.move_to_front(&1));
+    assert!(map.move_to_back(&1));
+    assert_eq!(keys(&map), [1]);
+}
+
+#[test]
+fn borrowed_keys() {
+    let mut map = LinkedHashMap::new();
+    map.insert(String::from("a"), 1);
+    map.insert(String::from("b"), 2);
+
+    assert_eq!(map.get("a"), Some(&1));
+    assert!(map.move_to_front("b"));
+    assert_eq!(map.remove("a"), Some(1));
+    assert_eq!(format!("{:?}", map), r#"{"b": 2}"#);
+}
+
+#[test]
+fn map_cursor() {
+    let mut map: LinkedHashMap<_, _> = (0..8).map(|i| (i, i)).collect();
+
+    {
+        let mut cursor = map.cursor();
+
+        while let Some(mut entry) = cursor.next() {
+            if *entry.key() % 2 == 0 {
+                assert_eq!(entry.remove().0 % 2, 0);
+            } else {
+                *entry.get_mut() *= 10;
+            }
+        }
+    }
+
+    assert_eq!(map.len(), 4);
+    assert!(map.iter().eq([(&1, &10), (&3, &30), (&5, &50), (&7, &70)].iter().cloned()));
+    assert!(!map.contains_key(&2));
+
+    // Entries inserted after using a cursor are still at the back
+    map.insert(2, 20);
+    assert_eq!(keys(&map), [1, 3, 5, 7, 2]);
+}
+
+#[test]
+fn moved_map() {
+    let mut maps = vec![LinkedHashMap::with_hasher(RandomState::new())];
+
+    for i in 0..4 {
+        maps[0].insert(i, i);
+    }
+
+    maps.reserve(64);
+    assert!(maps[0].move_to_back(&0));
+    let map = maps.swap_remove(0);
+    maps.push(map);
+    assert_eq!(maps[0].remove(&1), Some(1));
+    maps[0].insert(4, 4);
+    assert_eq!(keys(&maps[0]), [2, 3, 0, 4]);
+
+    // Move an empty map with only the tail marker
+    let mut map = LinkedHashMap::new();
+    map.insert(0, 0);
+    map.remove(&0);
+    let mut map = Box::new(map);
+    map.insert(1, 1);
+    assert!(map.move_to_front(&1));
+    assert_eq!(keys(&map), [1]);
+}
@@ -0,0 +1,474 @@
+// The reference model of a `TailList`, shared by the `model` test in
+// `lib_tests.rs` and the `api` fuzz target, which both include this file.
+//
+// The including file must import `mem`, `slice`, `Cursor`, `DetachedNode`,
+// `Marker`, `TailList` and `ValRef`, and implement `Check`.
+
+/// An operation of the model test, see `run_model`.
+#[derive(Clone, Debug)]
+enum Op {
+    /// Push a new value (only valid if no cursor is active).
+    Push,
+    /// Push a detached node, chosen by the index (only valid if no cursor is
+    /// active).
+    PushNode(usize),
+    /// Create a cursor over the list (only valid if no cursor is active).
+    Cursor,
+    /// Resume a marker, chosen by the index (only valid if no cursor is
+    /// active).
+    Resume(usize),
+    /// Purge the dummy nodes of leaked cursors (only valid if no cursor is
+    /// active).
+    Purge,
+    /// Drop a marker, chosen by the index.
+    DropMarker(usize),
+    /// Call `next` on the innermost cursor and apply the given operation to
+    /// the returned element.
+    Next(OnNext),
+    /// Call `next_pair` on the innermost cursor and apply the given operation
+    /// to the returned pair.
+    NextPair(OnPair),
+    /// Call `next_window` on the innermost cursor, with a size of the given
+    /// number modulo 4 plus 1, and remove the last element of the window if
+    /// the flag is set.
+    NextWindow(usize, bool),
+    /// Insert a detached node, chosen by the index, before the innermost
+    /// cursor.
+    InsertNode(usize),
+    /// Coalesce the elements of the innermost cursor, merging two elements
+    /// if their sum is divisible by the given number modulo 4 plus 2.
+    Coalesce(u64),
+    /// Apply the given operation to a passive item, chosen by the index.
+    Passive(usize, OnPassive),
+    /// Drop the innermost cursor.
+    End,
+    /// Turn the innermost cursor into a marker.
+    IntoMarker,
+    /// Leak the innermost cursor.
+    Forget,
+}
+
+/// An operation applied to an element returned by `Cursor::next`.
+#[derive(Clone, Debug)]
+enum OnNext {
+    Drop,
+    InsertBefore,
+    InsertAfter,
+    Remove,
+    Detach,
+    IntoPassive,
+    Tail,
+    IntoTail,
+}
+
+/// An operation applied to a pair returned by `Cursor::next_pair`.
+#[derive(Clone, Debug)]
+enum OnPair {
+    Drop,
+    RemoveSecond,
+    SwapValues,
+}
+
+/// An operation applied to a passive item.
+#[derive(Clone, Debug)]
+enum OnPassive {
+    InsertBefore,
+    InsertAfter,
+    Remove,
+    Detach,
+    Replace,
+}
+
+/// A node of the reference model. The level of a dummy node identifies its
+/// cursor, since there is only one active cursor per nesting level.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Item {
+    Val(u64),
+    Dummy(usize),
+    Marker(u64),
+    Leaked,
+}
+
+/// The reference model of a list, including the dummy nodes of all cursors
+/// and markers.
+struct Model {
+    items: Vec<Item>,
+    next_val: u64,
+    /// The markers of the list with their ids, see `Item::Marker`.
+    markers: Vec<(Marker<u64>, u64)>,
+    /// The nodes detached from the list with their values.
+    detached: Vec<(DetachedNode<u64>, u64)>,
+}
+
+/// Compares a list to the model, implemented by the including file.
+trait Check {
+    /// Returns a checker for `list`, which may be moved afterwards.
+    fn new(list: &TailList<u64>) -> Self;
+
+    /// Checks the list while cursors may be active.
+    fn check(&self, model: &Model);
+
+    /// Checks the list while no cursor is active.
+    fn check_idle(&self, list: &mut TailList<u64>, model: &Model);
+}
+
+impl Model {
+    /// Returns a new unique value, which is also used as the id of markers.
+    fn new_val(&mut self) -> u64 {
+        self.next_val += 1;
+        self.next_val
+    }
+
+    /// Returns the position of the given item.
+    fn pos(&self, item: Item) -> usize {
+        self.items.iter().position(|&i| i == item).expect("item not in model")
+    }
+
+    /// Returns the values of the next `k` elements of the cursor of the given
+    /// level without advancing it, if there are that many.
+    fn peek(&self, level: usize, k: usize) -> Option<Vec<u64>> {
+        let pos = self.pos(Item::Dummy(level));
+        let vals: Vec<u64> = self.items[pos + 1..].iter()
+            .filter_map(|&i| match i {
+                Item::Val(val) => Some(val),
+                _ => None,
+            })
+            .take(k)
+            .collect();
+
+        if vals.len() == k { Some(vals) } else { None }
+    }
+
+    /// Advances the cursor of the given level like `Cursor::next`.
+    fn next(&mut self, level: usize) -> Option<u64> {
+        let mut i = self.pos(Item::Dummy(level));
+
+        while i + 1 < self.items.len() {
+            self.items.swap(i, i + 1);
+            i += 1;
+
+            if let Item::Val(val) = self.items[i - 1] {
+                return Some(val);
+            }
+        }
+
+        None
+    }
+
+    /// Coalesces the elements of the cursor of the given level like
+    /// `Cursor::coalesce`.
+    fn coalesce<F>(&mut self, level: usize, merge: F) where F: Fn(u64, u64) -> bool {
+        while let Some(val) = self.next(level) {
+            while let Some(next) = self.peek(level, 1) {
+                if !merge(val, next[0]) {
+                    break;
+                }
+
+                let pos = self.pos(Item::Val(next[0]));
+                self.items.remove(pos);
+            }
+        }
+    }
+
+    /// Drops the marker chosen by `idx`, if there is any.
+    fn drop_marker(&mut self, idx: usize) {
+        if let Some((marker, id)) = take_at(&mut self.markers, idx) {
+            drop(marker);
+            let pos = self.pos(Item::Marker(id));
+            self.items.remove(pos);
+        }
+    }
+
+    /// Returns the contents of the model in the format of
+    /// `Validator::contents`.
+    fn contents(&self) -> Vec<Option<u64>> {
+        self.items.iter().map(|&i| match i {
+            Item::Val(val) => Some(val),
+            _ => None,
+        }).collect()
+    }
+}
+
+/// Removes and returns the item of `items` chosen by `idx`, if there is any.
+fn take_at<T>(items: &mut Vec<T>, idx: usize) -> Option<T> {
+    match items.len() {
+        0 => None,
+        len => Some(items.swap_remove(idx % len)),
+    }
+}
+
+/// Runs the given operations on a new list and the reference model, and
+/// checks the list with `C`.
+fn run_model<C: Check>(ops: &[Op]) {
+    let mut list = TailList::new();
+    let checker = C::new(&list);
+    let mut model = Model {
+        items: Vec::new(),
+        next_val: 0,
+        markers: Vec::new(),
+        detached: Vec::new(),
+    };
+    let mut ops = ops.iter();
+
+    while let Some(op) = ops.next() {
+        match *op {
+            Op::Push => {
+                let val = model.new_val();
+                list.push(val);
+                model.items.insert(0, Item::Val(val));
+            }
+            Op::PushNode(idx) => {
+                if let Some((node, val)) = take_at(&mut model.detached, idx) {
+                    list.push_node(node);
+                    model.items.insert(0, Item::Val(val));
+                }
+            }
+            Op::Cursor => {
+                let cursor = list.cursor();
+                model.items.insert(0, Item::Dummy(1));
+                checker.check(&model);
+
+                run_model_cursor(cursor, 1, &mut ops, Vec::new(), &checker, &mut model);
+            }
+            Op::Resume(idx) => {
+                if let Some((marker, id)) = take_at(&mut model.markers, idx) {
+                    let cursor = list.resume(marker);
+                    let pos = model.pos(Item::Marker(id));
+                    model.items[pos] = Item::Dummy(1);
+                    checker.check(&model);
+
+                    run_model_cursor(cursor, 1, &mut ops, Vec::new(), &checker, &mut model);
+                }
+            }
+            Op::Purge => {
+                let leaked = model.items.iter().filter(|&&i| i == Item::Leaked).count();
+                assert_eq!(list.purge_dummies(), leaked);
+                model.items.retain(|&i| i != Item::Leaked);
+            }
+            Op::DropMarker(idx) => model.drop_marker(idx),
+            _ => {}
+        }
+
+        checker.check_idle(&mut list, &model);
+    }
+
+    // Markers and detached nodes may outlive their list
+    drop(list);
+    drop(model);
+}
+
+/// Runs operations on `cursor`, whose nesting level is `level`, until an
+/// `Op::End`, `Op::IntoMarker` or `Op::Forget` is encountered. Passive items
+/// are dropped when the cursor is.
+fn run_model_cursor<'node, C: Check>(mut cursor: Cursor<'node, u64>, level: usize,
+                                     ops: &mut slice::Iter<Op>,
+                                     mut passive: Vec<(ValRef<'node, u64>, u64)>,
+                                     checker: &C, model: &mut Model) {
+    let mut end = &Op::End;
+
+    while let Some(op) = ops.next() {
+        match *op {
+            Op::Push | Op::PushNode(_) | Op::Cursor | Op::Resume(_) | Op::Purge => {}
+            Op::End | Op::IntoMarker | Op::Forget => {
+                end = op;
+                break;
+            }
+            Op::DropMarker(idx) => model.drop_marker(idx),
+            Op::Next(ref on_next) => {
+                let expected = model.next(level);
+                let mut next = match cursor.next() {
+                    Some(next) => next,
+                    None => {
+                        assert_eq!(expected, None);
+                        checker.check(model);
+                        continue;
+                    }
+                };
+
+                let val = *next;
+                assert_eq!(Some(val), expected);
+                checker.check(model);
+
+                match *on_next {
+                    OnNext::Drop => {}
+                    OnNext::InsertBefore => {
+                        let new = model.new_val();
+                        next.insert_before(new);
+                        let pos = model.pos(Item::Val(val));
+                        model.items.insert(pos, Item::Val(new));
+                    }
+                    OnNext::InsertAfter => {
+                        let new = model.new_val();
+                        next.insert_after(new);
+                        let pos = model.pos(Item::Val(val));
+                        model.items.insert(pos + 1, Item::Val(new));
+                    }
+                    OnNext::Remove => {
+                        assert_eq!(next.remove(), val);
+                        let pos = model.pos(Item::Val(val));
+                        model.items.remove(pos);
+                    }
+                    OnNext::Detach => {
+                        model.detached.push((next.detach(), val));
+                        let pos = model.pos(Item::Val(val));
+                        model.items.remove(pos);
+                    }
+                    OnNext::IntoPassive => {
+                        passive.push((next.into_passive(), val));
+                    }
+                    OnNext::Tail => {
+                        let (val_ref, tail) = next.tail();
+                        let pos = model.pos(Item::Val(val));
+                        model.items.insert(pos + 1, Item::Dummy(level + 1));
+                        checker.check(model);
+
+                        let passive = mem::take(&mut passive);
+                        run_model_cursor(tail, level + 1, ops, passive, checker, model);
+                        assert_eq!(**val_ref, val);
+                    }
+                    OnNext::IntoTail => {
+                        let (val_ref, tail) = next.into_tail();
+                        let pos = model.pos(Item::Val(val));
+                        model.items.insert(pos + 1, Item::Dummy(level + 1));
+                        checker.check(model);
+
+                        let mut passive = mem::take(&mut passive);
+                        passive.push((val_ref, val));
+                        run_model_cursor(tail, level + 1, ops, passive, checker, model);
+                    }
+                }
+            }
+            Op::NextPair(ref on_pair) => {
+                let expected = model.peek(level, 2);
+                let (mut first, mut second) = match cursor.next_pair() {
+                    Some(pair) => pair,
+                    None => {
+                        assert_eq!(expected, None);
+                        continue;
+                    }
+                };
+
+                let (a, b) = (*first, *second);
+                assert_eq!(Some(vec![a, b]), expected);
+                model.next(level);
+
+                match *on_pair {
+                    OnPair::Drop => {}
+                    OnPair::RemoveSecond => {
+                        assert_eq!(second.remove(), b);
+                        let pos = model.pos(Item::Val(b));
+                        model.items.remove(pos);
+                    }
+                    OnPair::SwapValues => {
+                        first.swap_values(&mut second);
+                        let (i, j) = (model.pos(Item::Val(a)), model.pos(Item::Val(b)));
+                        model.items.swap(i, j);
+                    }
+                }
+            }
+            Op::NextWindow(k, remove_last) => {
+                let k = k % 4 + 1;
+                let expected = model.peek(level, k);
+                let window = match k {
+                    1 => cursor.next_window::<1>().map(Vec::from),
+                    2 => cursor.next_window::<2>().map(Vec::from),
+                    3 => cursor.next_window::<3>().map(Vec::from),
+                    _ => cursor.next_window::<4>().map(Vec::from),
+                };
+
+                let mut window = match window {
+                    Some(window) => window,
+                    None => {
+                        assert_eq!(expected, None);
+                        continue;
+                    }
+                };
+
+                let vals: Vec<u64> = window.iter().map(|val_ref| **val_ref).collect();
+                assert_eq!(Some(vals), expected);
+                model.next(level);
+
+                if remove_last {
+                    let val = window.pop().expect("window is empty").remove();
+                    let pos = model.pos(Item::Val(val));
+                    model.items.remove(pos);
+                }
+            }
+            Op::InsertNode(idx) => {
+                if let Some((node, val)) = take_at(&mut model.detached, idx) {
+                    passive.push((cursor.insert_node(node), val));
+                    let pos = model.pos(Item::Dummy(level));
+                    model.items.insert(pos, Item::Val(val));
+                }
+            }
+            Op::Coalesce(k) => {
+                let k = k % 4 + 2;
+                cursor.coalesce(|a, b| (*a + *b) % k == 0);
+                model.coalesce(level, |a, b| (a + b) % k == 0);
+            }
+            Op::Passive(idx, ref on_passive) => {
+                if passive.is_empty() {
+                    continue;
+                }
+
+                let idx = idx % passive.len();
+                let val = passive[idx].1;
+                assert_eq!(*passive[idx].0, val);
+
+                match *on_passive {
+                    OnPassive::InsertBefore => {
+                        let new = model.new_val();
+                        passive[idx].0.insert_before(new);
+                        let pos = model.pos(Item::Val(val));
+                        model.items.insert(pos, Item::Val(new));
+                    }
+                    OnPassive::InsertAfter => {
+                        let new = model.new_val();
+                        passive[idx].0.insert_after(new);
+                        let pos = model.pos(Item::Val(val));
+                        model.items.insert(pos + 1, Item::Val(new));
+                    }
+                    OnPassive::Remove => {
+                        let (val_ref, _) = passive.swap_remove(idx);
+                        assert_eq!(val_ref.remove(), val);
+                        let pos = model.pos(Item::Val(val));
+                        model.items.remove(pos);
+                    }
+                    OnPassive::Detach => {
+                        let (val_ref, _) = passive.swap_remove(idx);
+                        model.detached.push((val_ref.detach(), val));
+                        let pos = model.pos(Item::Val(val));
+                        model.items.remove(pos);
+                    }
+                    OnPassive::Replace => {
+                        let new = model.new_val();
+                        *passive[idx].0 = new;
+                        passive[idx].1 = new;
+                        let pos = model.pos(Item::Val(val));
+                        model.items[pos] = Item::Val(new);
+                    }
+                }
+            }
+        }
+
+        checker.check(model);
+    }
+
+    let pos = model.pos(Item::Dummy(level));
+
+    match *end {
+        Op::IntoMarker => {
+            let id = model.new_val();
+            model.markers.push((cursor.into_marker(), id));
+            model.items[pos] = Item::Marker(id);
+        }
+        Op::Forget => {
+            mem::forget(cursor);
+            model.items[pos] = Item::Leaked;
+        }
+        _ => {
+            drop(cursor);
+            model.items.remove(pos);
+        }
+    }
+}
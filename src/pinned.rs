@@ -0,0 +1,191 @@
+//! A tail list whose elements are pinned, built on `TailList`.
+//!
+//! The value of an element of a `TailList` is never moved by the list itself,
+//! but `ValRef`s can move it out through `DerefMut`, `replace`, `take`,
+//! `swap_values` or `remove`. A `PinnedList` only gives out `PinnedValRef`s,
+//! which provide shared access to an element and mutable access only through
+//! `Pin<&mut T>`, and which drop a removed element in place. Every element of
+//! a `PinnedList` therefore stays at the same address until it is dropped, so
+//! it may be `!Unpin`, e.g. a future or a value referencing itself.
+//!
+//! For `Unpin` elements, a `PinnedList` offers nothing over a `TailList`.
+
+use allocator_api2::alloc::{Allocator, Global};
+use core::ops::Deref;
+use core::pin::Pin;
+#[cfg(target_has_atomic = "ptr")]
+use super::{Error, Handle};
+use super::{discard, release_slot, val_ptr, Cursor, ReadCursor, TailList, ValRef};
+
+////////////////////////////////////////////////////////////////////////////////
+// STRUCTS
+////////////////////////////////////////////////////////////////////////////////
+
+/// A tail list of pinned elements (see the module documentation).
+pub struct PinnedList<T, A: Allocator + Clone = Global> {
+    list: TailList<T, A>,
+}
+
+/// A cursor over the elements of a `PinnedList`, see `Cursor`.
+///
+/// Unlike a `Cursor`, it is an `Iterator`: the elements it returns do not
+/// borrow it, since a `PinnedValRef` cannot be turned into a cursor over its
+/// tail.
+pub struct PinnedCursor<'node, T: 'node, A: 'node + Allocator + Clone = Global> {
+    cursor: Cursor<'node, T, A>,
+}
+
+/// A passive item, which provides pinned access to a single element of a
+/// `PinnedList`.
+///
+/// Unlike a `ValRef`, it does not implement `DerefMut`, so the element cannot
+/// be moved out of it:
+///
+/// ```compile_fail
+/// use linked_tail_list::pinned::PinnedList;
+///
+/// let mut list = PinnedList::new();
+/// list.push(1);
+///
+/// let mut cursor = list.cursor();
+/// let mut val = cursor.next().unwrap();
+/// *val = 2;
+/// ```
+pub struct PinnedValRef<'node, T: 'node, A: 'node + Allocator + Clone = Global> {
+    val_ref: ValRef<'node, T, A>,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// IMPLS
+////////////////////////////////////////////////////////////////////////////////
+
+impl<T> PinnedList<T> {
+    /// Returns a new, empty list.
+    pub fn new() -> PinnedList<T> {
+        PinnedList { list: TailList::new() }
+    }
+}
+
+impl<T, A: Allocator + Clone> PinnedList<T, A> {
+    /// Returns a new, empty list, which allocates its nodes from `alloc` (see
+    /// `TailList::new_in`).
+    pub fn new_in(alloc: A) -> PinnedList<T, A> {
+        PinnedList { list: TailList::new_in(alloc) }
+    }
+
+    /// Returns the allocator of this list.
+    pub fn allocator(&self) -> &A {
+        self.list.allocator()
+    }
+
+    /// Pushes a new element to the front of the list and returns it pinned.
+    pub fn push(&mut self, val: T) -> Pin<&mut T> {
+        // Elements of a `PinnedList` are never moved, see `PinnedValRef`
+        unsafe { self.list.push_pinned(val) }
+    }
+
+    /// Returns a cursor over all elements in this list.
+    pub fn cursor<'node>(&'node mut self) -> PinnedCursor<'node, T, A> {
+        PinnedCursor { cursor: self.list.cursor() }
+    }
+
+    /// Returns a read-only cursor over all elements in this list.
+    pub fn read_cursor<'node>(&'node self) -> ReadCursor<'node, T, A> {
+        self.list.read_cursor()
+    }
+
+    /// Returns a `PinnedValRef` to the element identified by `handle`, or
+    /// `Error::Stale` if that element is no longer part of this list, see
+    /// `TailList::try_get`.
+    #[cfg(target_has_atomic = "ptr")]
+    pub fn try_get<'node>(&'node mut self, handle: Handle<T, A>)
+                          -> Result<PinnedValRef<'node, T, A>, Error> {
+        self.list.try_get(handle).map(PinnedValRef::new)
+    }
+
+    /// Removes the dummy nodes of leaked cursors from this list and returns
+    /// their number, see `TailList::purge_dummies`.
+    pub fn purge_dummies(&mut self) -> usize {
+        self.list.purge_dummies()
+    }
+
+    /// Returns the number of dummy nodes in this list, which includes those of
+    /// leaked cursors.
+    pub fn dummy_count(&self) -> usize {
+        self.list.dummy_count()
+    }
+}
+
+impl<'node, T: 'node, A: 'node + Allocator + Clone> PinnedValRef<'node, T, A> {
+    /// Returns a new `PinnedValRef` wrapping `val_ref`, which must reference an
+    /// element of a `PinnedList`.
+    fn new(val_ref: ValRef<'node, T, A>) -> PinnedValRef<'node, T, A> {
+        PinnedValRef { val_ref }
+    }
+
+    /// Returns this element pinned.
+    pub fn as_mut(&mut self) -> Pin<&mut T> {
+        // No method of this type or of `PinnedList` moves an element, so it
+        // stays put until it is dropped
+        unsafe { Pin::new_unchecked(&mut *val_ptr(&self.val_ref.node)) }
+    }
+
+    /// Inserts a new element before this element and returns a `PinnedValRef`
+    /// to the newly inserted element.
+    pub fn insert_before(&mut self, val: T) -> PinnedValRef<'node, T, A> {
+        PinnedValRef::new(self.val_ref.insert_before(val))
+    }
+
+    /// Inserts a new element after this element and returns a `PinnedValRef`
+    /// to the newly inserted element.
+    pub fn insert_after(&mut self, val: T) -> PinnedValRef<'node, T, A> {
+        PinnedValRef::new(self.val_ref.insert_after(val))
+    }
+
+    /// Removes this element from the list and drops it in place.
+    pub fn remove(self) {
+        release_slot(&self.val_ref.shared, &self.val_ref.node);
+        discard(self.val_ref.node);
+    }
+
+    /// Returns a `Handle` to this element, see `ValRef::handle`.
+    #[cfg(target_has_atomic = "ptr")]
+    pub fn handle(&self) -> Handle<T, A> {
+        self.val_ref.handle()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// TRAIT IMPLS
+////////////////////////////////////////////////////////////////////////////////
+
+impl<T> Default for PinnedList<T> {
+    fn default() -> PinnedList<T> {
+        PinnedList::new()
+    }
+}
+
+impl<'node, T: 'node, A: 'node + Allocator + Clone> Iterator
+    for PinnedCursor<'node, T, A>
+{
+    type Item = PinnedValRef<'node, T, A>;
+
+    fn next(&mut self) -> Option<PinnedValRef<'node, T, A>> {
+        self.cursor.next().map(|val| PinnedValRef::new(val.into_passive()))
+    }
+}
+
+impl<'node, T: 'node, A: 'node + Allocator + Clone> Deref
+    for PinnedValRef<'node, T, A>
+{
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.val_ref.deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    include!( "./pinned_tests.rs");
+}
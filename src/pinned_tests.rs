@@ -0,0 +1,111 @@
+use std::cell::Cell;
+use std::marker::PhantomPinned;
+use std::pin::Pin;
+use std::ptr;
+use std::rc::Rc;
+use super::PinnedList;
+#[cfg(target_has_atomic = "ptr")]
+use super::Error;
+
+include!("./self_ref_tests.rs");
+
+const N: usize = if cfg!(miri) { 32 } else { 1024 };
+
+#[test]
+fn pinned_elements() {
+    let drops = Rc::new(Cell::new(0));
+    let mut list = PinnedList::new();
+
+    for _ in 0..N {
+        list.push(SelfRef::new(&drops)).init();
+    }
+
+    // Remove every third element and insert new ones around the others, which
+    // allocates nodes next to the pinned elements
+    for (i, mut val) in list.cursor().enumerate() {
+        val.check();
+
+        match i % 3 {
+            0 => val.remove(),
+            1 => val.insert_before(SelfRef::new(&drops)).as_mut().init(),
+            _ => val.insert_after(SelfRef::new(&drops)).as_mut().init(),
+        }
+    }
+
+    assert_eq!(drops.get(), N.div_ceil(3));
+    assert_eq!(list.read_cursor().count(), N - N.div_ceil(3) + N - N.div_ceil(3));
+
+    for val in list.read_cursor() {
+        val.check();
+    }
+
+    // The elements of a cursor do not borrow it, so they can be held at once
+    let len = list.read_cursor().count();
+    let vals: Vec<_> = list.cursor().collect();
+    assert_eq!(vals.len(), len);
+
+    for val in vals.into_iter().step_by(2) {
+        val.remove();
+    }
+
+    assert_eq!(drops.get(), N.div_ceil(3) + len.div_ceil(2));
+
+    drop(list);
+    assert_eq!(drops.get(), 2 * N - N.div_ceil(3));
+}
+
+#[test]
+fn leaked_cursor() {
+    let drops = Rc::new(Cell::new(0));
+    let mut list = PinnedList::new();
+    list.push(SelfRef::new(&drops)).init();
+    list.push(SelfRef::new(&drops)).init();
+
+    {
+        let mut cursor = list.cursor();
+        cursor.next().unwrap().check();
+        std::mem::forget(cursor);
+    }
+
+    assert_eq!(list.dummy_count(), 1);
+    assert_eq!(list.purge_dummies(), 1);
+
+    let mut cursor = list.cursor();
+    cursor.next().unwrap().as_mut().check();
+    cursor.next().unwrap().remove();
+    assert!(cursor.next().is_none());
+    assert_eq!(drops.get(), 1);
+}
+
+#[cfg(target_has_atomic = "ptr")]
+#[test]
+fn handles() {
+    let drops = Rc::new(Cell::new(0));
+    let mut list = PinnedList::new();
+    list.push(SelfRef::new(&drops)).init();
+    list.push(SelfRef::new(&drops)).init();
+
+    let (first, second) = {
+        let mut cursor = list.cursor();
+        let first = cursor.next().unwrap().handle();
+        (first, cursor.next().unwrap().handle())
+    };
+
+    list.try_get(first).unwrap().remove();
+    assert_eq!(list.try_get(first).err(), Some(Error::Stale));
+    assert_eq!(drops.get(), 1);
+
+    let mut val = list.try_get(second).unwrap();
+    val.as_mut().check();
+    val.insert_after(SelfRef::new(&drops)).as_mut().init();
+
+    // A handle of another list does not resolve to an element of this one
+    let mut other = PinnedList::new();
+    other.push(SelfRef::new(&drops));
+    let handle = other.cursor().next().unwrap().handle();
+    assert_eq!(list.try_get(handle).err(), Some(Error::Stale));
+
+    for val in list.read_cursor() {
+        val.check();
+    }
+}
@@ -0,0 +1,14 @@
+// A deterministic pseudo random number generator, shared by the tests in
+// `cache_tests.rs` and `timer_tests.rs`, which both include this file.
+
+/// A simple deterministic pseudo random number generator (xorshift).
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
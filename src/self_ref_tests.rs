@@ -0,0 +1,35 @@
+// A self-referential test value, shared by the tests in `lib_tests.rs` and
+// `pinned_tests.rs`, which both include this file.
+
+/// A self-referential value, which checks that it is not moved once pinned.
+#[derive(Debug)]
+struct SelfRef {
+    this: *const SelfRef,
+    drops: Rc<Cell<usize>>,
+    _pin: PhantomPinned,
+}
+
+impl SelfRef {
+    fn new(drops: &Rc<Cell<usize>>) -> SelfRef {
+        SelfRef { this: ptr::null(), drops: drops.clone(), _pin: PhantomPinned }
+    }
+
+    fn init(self: Pin<&mut SelfRef>) {
+        let this = unsafe { self.get_unchecked_mut() };
+        this.this = this;
+    }
+
+    fn check(&self) {
+        assert!(ptr::eq(self.this, self), "pinned value has been moved");
+    }
+}
+
+impl Drop for SelfRef {
+    fn drop(&mut self) {
+        if !self.this.is_null() {
+            self.check();
+        }
+
+        self.drops.set(self.drops.get() + 1);
+    }
+}
@@ -0,0 +1,401 @@
+//! A hierarchical timer wheel, whose slots are tail lists.
+//!
+//! A `TimerWheel` has `LEVELS` levels of `SLOTS` slots each. A slot of level 0
+//! covers a single tick, while a slot of level `n + 1` covers a whole turn of
+//! the slots of level `n`. A timer is stored in the lowest level which still
+//! covers its deadline, and is moved to the lower levels (cascaded) while time
+//! advances. Timers are moved between slots by relinking their nodes, so they
+//! are never reallocated.
+//!
+//! Every timer has an index into a table of the wheel, which references its
+//! node and counts the generations of the index: the generation is
+//! incremented whenever the timer of the index expires or is cancelled.
+//! Timers are identified by `TimerHandle`s, which store the index and
+//! generation of their timer, so they are resolved without touching a node
+//! which may have been freed. Cancelling and rescheduling a timer therefore
+//! takes O(1) time.
+//!
+//! The wheel keeps up to `POOL_LIMIT` nodes of expired and cancelled timers
+//! for new timers, together with their indices, and frees the others.
+
+use alloc::vec::Vec;
+use allocator_api2::alloc::Global;
+use core::cmp;
+use core::marker::PhantomData;
+use super::{detach, insert_at, link_at, unique_id, val_ptr, DetachedNode, NodeRef, OwnRef,
+            TailList};
+
+/// The number of bits of a deadline which select the slot within a level.
+const SLOT_BITS: usize = 6;
+
+/// The number of slots per level.
+pub const SLOTS: usize = 1 << SLOT_BITS;
+
+/// The number of levels, which are enough to cover all `u64` deadlines.
+pub const LEVELS: usize = 64_usize.div_ceil(SLOT_BITS);
+
+/// The maximum number of nodes of expired and cancelled timers a wheel keeps
+/// for new timers.
+pub const POOL_LIMIT: usize = 256;
+
+////////////////////////////////////////////////////////////////////////////////
+// STRUCTS
+////////////////////////////////////////////////////////////////////////////////
+
+/// A hierarchical timer wheel (see the module documentation).
+///
+/// Time is measured in ticks of arbitrary length. The wheel starts at tick 0.
+pub struct TimerWheel<T> {
+    /// The list of every slot. A list is only created when a timer is linked
+    /// into its slot for the first time, since creating a list allocates its
+    /// head.
+    slots: Vec<Option<TailList<Entry<T>>>>,
+    /// A bit mask of the non-empty slots per level.
+    occupied: [u64; LEVELS],
+    elapsed: u64,
+    len: usize,
+    /// The table of timers, by index.
+    timers: Vec<Timer<T>>,
+    /// The indices of `timers` whose node has been freed.
+    vacant: Vec<usize>,
+    /// The nodes of expired and cancelled timers, whose values are `None`.
+    pool: Vec<DetachedNode<Entry<T>>>,
+    /// The id of this wheel (see `unique_id`), which is never given to
+    /// another wheel, even after this one has been dropped.
+    id: usize,
+}
+
+/// An entry of `TimerWheel::timers`. `node` is `None` iff the node of the
+/// index has been freed, and `generation` counts how often the timer of the
+/// index has expired or been cancelled.
+struct Timer<T> {
+    node: Option<NodeRef<Entry<T>, Global>>,
+    generation: u64,
+}
+
+/// The element of a `TimerWheel` for a single timer. `slot` is the index of
+/// the list it is linked into, and `index` its index in `TimerWheel::timers`.
+struct Entry<T> {
+    deadline: u64,
+    slot: usize,
+    index: usize,
+    val: Option<T>,
+}
+
+/// A `TimerHandle` identifies a timer of a `TimerWheel` until the timer has
+/// expired or has been cancelled.
+pub struct TimerHandle<T> {
+    index: usize,
+    generation: u64,
+    wheel: usize,
+    phantom: PhantomData<*const Entry<T>>,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// IMPLS
+////////////////////////////////////////////////////////////////////////////////
+
+impl<T> TimerWheel<T> {
+    /// Returns a new, empty timer wheel.
+    ///
+    /// This only allocates the table of the `LEVELS * SLOTS` slots: the list
+    /// of a slot is created when a timer is linked into the slot for the first
+    /// time, and kept until the wheel is dropped.
+    pub fn new() -> TimerWheel<T> {
+        let slots: Vec<_> = (0..LEVELS * SLOTS).map(|_| None).collect();
+
+        TimerWheel {
+            slots,
+            occupied: [0; LEVELS],
+            elapsed: 0,
+            len: 0,
+            timers: Vec::new(),
+            vacant: Vec::new(),
+            pool: Vec::new(),
+            id: unique_id(),
+        }
+    }
+
+    /// Returns the current tick, i.e. the latest `now` passed to `advance`.
+    pub fn now(&self) -> u64 {
+        self.elapsed
+    }
+
+    /// Returns the number of pending timers.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether there are no pending timers.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Adds a timer which expires at `deadline` and returns a handle to it. If
+    /// the deadline has already passed, the timer expires on the next call to
+    /// `advance`.
+    pub fn insert(&mut self, deadline: u64, val: T) -> TimerHandle<T> {
+        let node_ref = match self.pool.pop() {
+            Some(node) => {
+                let node_ref = node.into_ref();
+
+                unsafe {
+                    let entry = &mut *val_ptr(&node_ref);
+                    entry.deadline = deadline;
+                    entry.val = Some(val);
+                }
+
+                self.link(&node_ref);
+                node_ref
+            }
+            None => {
+                let index = match self.vacant.pop() {
+                    Some(index) => index,
+                    None => {
+                        self.timers.push(Timer { node: None, generation: 0 });
+                        self.timers.len() - 1
+                    }
+                };
+
+                let entry = Entry { deadline, slot: 0, index, val: Some(val) };
+                let slot = self.slot_for(deadline);
+                let node_ref = insert_at(&self.slot_list(slot).head, Some(entry), Global);
+                self.link_slot(&node_ref, slot);
+                self.timers[index].node = Some(node_ref.clone());
+                node_ref
+            }
+        };
+
+        self.len += 1;
+        let index = unsafe { (*val_ptr(&node_ref)).index };
+
+        TimerHandle {
+            index,
+            generation: self.timers[index].generation,
+            wheel: self.id,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Cancels the timer of `handle` and returns its value, unless it has
+    /// already expired or been cancelled.
+    pub fn cancel(&mut self, handle: TimerHandle<T>) -> Option<T> {
+        let node_ref = self.resolve(handle)?;
+        self.unlink(&node_ref);
+        Some(self.release(node_ref))
+    }
+
+    /// Changes the deadline of the timer of `handle` and returns whether the
+    /// timer is still pending. The handle stays valid.
+    pub fn reschedule(&mut self, handle: TimerHandle<T>, deadline: u64) -> bool {
+        let node_ref = match self.resolve(handle) {
+            Some(node_ref) => node_ref,
+            None => return false,
+        };
+
+        self.unlink(&node_ref);
+        unsafe { (*val_ptr(&node_ref)).deadline = deadline; }
+        self.link(&node_ref);
+        true
+    }
+
+    /// Returns the deadline of the timer of `handle`, unless it has already
+    /// expired or been cancelled.
+    pub fn deadline(&self, handle: TimerHandle<T>) -> Option<u64> {
+        self.resolve(handle).map(|node_ref| unsafe { (*val_ptr(&node_ref)).deadline })
+    }
+
+    /// Advances the wheel to the tick `now` and returns the values of all timers
+    /// which have expired in the meantime, ordered by their deadlines. Timers
+    /// expiring at the same tick are returned in no particular order.
+    ///
+    /// The slots are drained with a `Cursor`: expired timers are detached from
+    /// their slot, while the others are relinked into a lower level.
+    pub fn advance(&mut self, now: u64) -> Vec<T> {
+        // The expired timers with their deadlines
+        let mut expired = Vec::new();
+
+        while let Some((slot, at)) = self.next_expiration() {
+            if at > now {
+                break;
+            }
+
+            self.elapsed = at;
+            self.occupied[slot / SLOTS] &= !(1 << (slot % SLOTS));
+
+            // Timers which have not expired yet are linked into lower levels,
+            // never into the drained slot
+            let mut list = self.slots[slot].take().expect("occupied slot has no list");
+            let mut cursor = list.cursor();
+            let start = expired.len();
+
+            while let Some(val) = cursor.next() {
+                let node_ref = val.detach().into_ref();
+                let deadline = unsafe { (*val_ptr(&node_ref)).deadline };
+
+                if deadline <= at {
+                    expired.push((deadline, self.release(node_ref)));
+                } else {
+                    self.link(&node_ref);
+                }
+            }
+
+            // Timers whose deadline had already passed when they were linked
+            // share the slot of the tick at that time (see `slot_for`)
+            expired[start..].sort_by_key(|&(deadline, _)| deadline);
+            drop(cursor);
+            self.slots[slot] = Some(list);
+        }
+
+        self.elapsed = cmp::max(self.elapsed, now);
+        expired.into_iter().map(|(_, val)| val).collect()
+    }
+
+    /// Returns the index of the next non-empty slot and the tick at which it
+    /// starts, if any.
+    fn next_expiration(&self) -> Option<(usize, u64)> {
+        for level in 0..LEVELS {
+            let occupied = self.occupied[level];
+
+            if occupied == 0 {
+                continue;
+            }
+
+            // Slots before the current one are always empty (see `level_for`)
+            let shift = level * SLOT_BITS;
+            let pos = (self.elapsed >> shift) as usize % SLOTS;
+            debug_assert!(occupied >> pos != 0, "timer missed");
+            let slot = pos + (occupied >> pos).trailing_zeros() as usize;
+
+            let base = self.elapsed & u64::MAX.checked_shl((shift + SLOT_BITS) as u32).unwrap_or(0);
+            return Some((level * SLOTS + slot, base + ((slot as u64) << shift)));
+        }
+
+        None
+    }
+
+    /// Returns the index of the slot for a timer with the given deadline.
+    fn slot_for(&self, deadline: u64) -> usize {
+        let deadline = cmp::max(deadline, self.elapsed);
+        let level = level_for(self.elapsed, deadline);
+        level * SLOTS + (deadline >> (level * SLOT_BITS)) as usize % SLOTS
+    }
+
+    /// Returns the list of `slot`, which is created if no timer has been
+    /// linked into the slot yet.
+    fn slot_list(&mut self, slot: usize) -> &TailList<Entry<T>> {
+        self.slots[slot].get_or_insert_with(TailList::new)
+    }
+
+    /// Links the given unlinked node into the slot for its deadline.
+    fn link(&mut self, node_ref: &NodeRef<Entry<T>, Global>) {
+        let slot = self.slot_for(unsafe { (*val_ptr(node_ref)).deadline });
+        link_at(&self.slot_list(slot).head, node_ref);
+        self.link_slot(node_ref, slot);
+    }
+
+    /// Records that the given node has been linked into `slot`.
+    fn link_slot(&mut self, node_ref: &NodeRef<Entry<T>, Global>, slot: usize) {
+        unsafe { (*val_ptr(node_ref)).slot = slot; }
+        self.occupied[slot / SLOTS] |= 1 << (slot % SLOTS);
+    }
+
+    /// Unlinks the given node from its slot.
+    fn unlink(&mut self, node_ref: &NodeRef<Entry<T>, Global>) {
+        let slot = unsafe { (*val_ptr(node_ref)).slot };
+        detach(node_ref);
+
+        let list = self.slots[slot].as_ref().expect("linked slot has no list");
+
+        if list.head.borrow_inner().opt_node_ref().is_none() {
+            self.occupied[slot / SLOTS] &= !(1 << (slot % SLOTS));
+        }
+    }
+
+    /// Returns the value of the given unlinked node and keeps the node for a
+    /// new timer, unless the pool is full. All handles to the timer become
+    /// stale.
+    fn release(&mut self, node_ref: NodeRef<Entry<T>, Global>) -> T {
+        let (index, val) = unsafe {
+            let entry = &mut *val_ptr(&node_ref);
+            (entry.index, entry.val.take())
+        };
+
+        self.timers[index].generation += 1;
+        let node = DetachedNode { node: node_ref };
+
+        if self.pool.len() < POOL_LIMIT {
+            self.pool.push(node);
+        } else {
+            // Free the node
+            self.timers[index].node = None;
+            self.vacant.push(index);
+            drop(node);
+        }
+
+        self.len -= 1;
+        val.expect("released timer has no value")
+    }
+
+    /// Returns a reference to the node of the timer of `handle`, if it is
+    /// still pending.
+    fn resolve(&self, handle: TimerHandle<T>) -> Option<NodeRef<Entry<T>, Global>> {
+        if handle.wheel != self.id {
+            return None;
+        }
+
+        let timer = self.timers.get(handle.index)?;
+
+        if timer.generation != handle.generation {
+            return None;
+        }
+
+        timer.node.clone()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// FUNCTIONS
+////////////////////////////////////////////////////////////////////////////////
+
+/// Returns the level of a timer with the given deadline, which must not have
+/// passed yet: the lowest level whose current turn covers the deadline, i.e.
+/// for which the deadline only differs from `elapsed` in the bits which select
+/// the slot of the level or a lower one.
+fn level_for(elapsed: u64, deadline: u64) -> usize {
+    let masked = (elapsed ^ deadline) | (SLOTS as u64 - 1);
+    let significant = 63 - masked.leading_zeros() as usize;
+    significant / SLOT_BITS
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// TRAIT IMPLS
+////////////////////////////////////////////////////////////////////////////////
+
+impl<T> Default for TimerWheel<T> {
+    fn default() -> TimerWheel<T> {
+        TimerWheel::new()
+    }
+}
+
+impl<T> Clone for TimerHandle<T> {
+    fn clone(&self) -> TimerHandle<T> {
+        *self
+    }
+}
+
+impl<T> Copy for TimerHandle<T> {}
+
+impl<T> PartialEq for TimerHandle<T> {
+    fn eq(&self, other: &TimerHandle<T>) -> bool {
+        self.index == other.index && self.generation == other.generation
+            && self.wheel == other.wheel
+    }
+}
+
+impl<T> Eq for TimerHandle<T> {}
+
+#[cfg(test)]
+mod tests {
+    include!( "./timer_tests.rs");
+}
@@ -0,0 +1,225 @@
+use super::{TimerWheel, POOL_LIMIT, SLOTS};
+
+include!("./rng_tests.rs");
+
+/// Returns whether all timers are linked into the slot they claim to be in and
+/// the occupied bit masks are correct.
+fn consistent<T>(wheel: &TimerWheel<T>) -> bool {
+    let mut count = 0;
+
+    for (slot, list) in wheel.slots.iter().enumerate() {
+        let occupied = wheel.occupied[slot / SLOTS] & 1 << (slot % SLOTS) != 0;
+
+        let list = match *list {
+            Some(ref list) => list,
+            None if occupied => return false,
+            None => continue,
+        };
+
+        if occupied != list.read_cursor().next().is_some()
+            || list.read_cursor().any(|entry| entry.slot != slot || entry.val.is_none())
+        {
+            return false;
+        }
+
+        count += list.read_cursor().count();
+    }
+
+    count == wheel.len()
+}
+
+#[test]
+fn expire_in_order() {
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    let mut wheel = TimerWheel::new();
+    let mut model = Vec::new();
+
+    // Deadlines in all levels, with many at the same tick
+    for _ in 0..4096 {
+        let deadline = match rng.next() % 4 {
+            0 => rng.next() % 256,
+            1 => rng.next() % (1 << 16),
+            2 => rng.next() % (1 << 24),
+            _ => rng.next() >> (rng.next() % 64),
+        };
+
+        wheel.insert(deadline, deadline);
+        model.push(deadline);
+    }
+
+    assert!(consistent(&wheel));
+
+    let mut now = 0;
+
+    while !model.is_empty() {
+        now = match rng.next() % 3 {
+            0 => now + rng.next() % 64,
+            1 => now.saturating_add(rng.next() % (1 << 20)),
+            _ => now.saturating_add(rng.next() >> (rng.next() % 64)),
+        };
+
+        let expired = wheel.advance(now);
+        assert!(expired.windows(2).all(|w| w[0] <= w[1]), "unordered");
+        assert_eq!(wheel.now(), now);
+
+        let mut expected: Vec<_> = model.iter().cloned().filter(|&d| d <= now).collect();
+        expected.sort();
+        model.retain(|&d| d > now);
+
+        assert_eq!(expired, expected);
+        assert_eq!(wheel.len(), model.len());
+        assert!(consistent(&wheel));
+    }
+
+    assert!(wheel.is_empty());
+    assert!(wheel.advance(u64::MAX).is_empty());
+}
+
+#[test]
+fn past_deadlines() {
+    let mut wheel = TimerWheel::new();
+    assert_eq!(wheel.advance(1000), Vec::<u64>::new());
+
+    wheel.insert(10, 10);
+    wheel.insert(1000, 1000);
+    wheel.insert(5, 5);
+    wheel.insert(1001, 1001);
+
+    // Timers whose deadline has passed expire on the next advance, ordered by
+    // their deadlines
+    assert_eq!(wheel.advance(1000), [5, 10, 1000]);
+    assert_eq!(wheel.advance(1001), [1001]);
+    assert!(wheel.is_empty());
+}
+
+#[test]
+fn lazy_slot_lists() {
+    let mut wheel = TimerWheel::new();
+    let lists = |wheel: &TimerWheel<u64>| wheel.slots.iter().filter(|l| l.is_some()).count();
+    assert_eq!(lists(&wheel), 0);
+
+    // The list of a slot is created for its first timer and kept afterwards
+    let handle = wheel.insert(100, 100);
+    assert_eq!(lists(&wheel), 1);
+    assert_eq!(wheel.cancel(handle), Some(100));
+    wheel.insert(100, 100);
+    assert_eq!(lists(&wheel), 1);
+    assert!(consistent(&wheel));
+
+    // The timer is cascaded from level 1 into a slot of level 0
+    assert_eq!(wheel.advance(100), [100]);
+    assert_eq!(lists(&wheel), 2);
+    assert!(consistent(&wheel));
+}
+
+#[test]
+fn cancel_and_reschedule() {
+    let mut wheel = TimerWheel::new();
+    let handles: Vec<_> = (0..8).map(|i| wheel.insert(i * 100, i)).collect();
+
+    assert_eq!(wheel.cancel(handles[3]), Some(3));
+    assert_eq!(wheel.cancel(handles[3]), None);
+    assert_eq!(wheel.deadline(handles[3]), None);
+    assert!(!wheel.reschedule(handles[3], 0));
+    assert_eq!(wheel.len(), 7);
+    assert!(consistent(&wheel));
+
+    // Move timers to an earlier and a later deadline
+    assert!(wheel.reschedule(handles[5], 150));
+    assert!(wheel.reschedule(handles[1], 1 << 30));
+    assert_eq!(wheel.deadline(handles[5]), Some(150));
+    assert!(consistent(&wheel));
+
+    assert_eq!(wheel.advance(200), [0, 5, 2]);
+    assert_eq!(wheel.cancel(handles[2]), None);
+    assert_eq!(wheel.advance(800), [4, 6, 7]);
+
+    // Rescheduling keeps the handle valid, even into the past
+    assert!(wheel.reschedule(handles[1], 0));
+    assert_eq!(wheel.deadline(handles[1]), Some(0));
+    assert_eq!(wheel.advance(800), [1]);
+    assert!(wheel.is_empty());
+    assert!(consistent(&wheel));
+}
+
+#[test]
+fn foreign_handles() {
+    let mut a = TimerWheel::new();
+    let mut b = TimerWheel::new();
+    let handle = a.insert(10, 0);
+    b.insert(10, 1);
+
+    assert_eq!(b.cancel(handle), None);
+    assert_eq!(b.deadline(handle), None);
+    assert_eq!(a.cancel(handle), Some(0));
+}
+
+#[test]
+fn dropped_wheel_handles() {
+    let mut old = TimerWheel::new();
+    old.insert(10, "a");
+    let handle = old.insert(20, "a");
+    drop(old);
+
+    // The new wheel likely reuses the memory of the old one, and its timer has
+    // the same index and generation as the handle
+    let mut new = TimerWheel::new();
+    new.insert(10, "b");
+    new.insert(20, "b");
+
+    assert_eq!(new.cancel(handle), None);
+    assert_eq!(new.deadline(handle), None);
+    assert!(!new.reschedule(handle, 30));
+    assert_eq!(new.len(), 2);
+}
+
+#[test]
+fn reuse_nodes() {
+    let mut wheel = TimerWheel::new();
+    let old: Vec<_> = (0..4).map(|i| wheel.insert(i, i)).collect();
+    assert_eq!(wheel.advance(4).len(), 4);
+    assert_eq!(wheel.pool.len(), 4);
+
+    // New timers reuse the nodes, but not the handles of the old timers
+    let new: Vec<_> = (0..4).map(|i| wheel.insert(i + 10, i + 10)).collect();
+    assert!(wheel.pool.is_empty());
+
+    for (&old, &new) in old.iter().zip(&new) {
+        assert!(old != new);
+        assert_eq!(wheel.cancel(old), None);
+        assert!(!wheel.reschedule(old, 100));
+    }
+
+    assert_eq!(wheel.len(), 4);
+    assert_eq!(wheel.cancel(new[0]), Some(10));
+    assert_eq!(wheel.advance(100), [11, 12, 13]);
+}
+
+#[test]
+fn pool_limit() {
+    let mut wheel = TimerWheel::new();
+    let n = POOL_LIMIT + 16;
+    let old: Vec<_> = (0..n as u64).map(|i| wheel.insert(i, i)).collect();
+
+    // Only `POOL_LIMIT` nodes are kept, the others are freed
+    assert_eq!(wheel.advance(n as u64).len(), n);
+    assert_eq!(wheel.pool.len(), POOL_LIMIT);
+    assert_eq!(wheel.vacant.len(), 16);
+
+    // New timers reuse the indices of the freed nodes
+    let new: Vec<_> = (0..n as u64).map(|i| wheel.insert(n as u64 + i, i)).collect();
+    assert!(wheel.pool.is_empty() && wheel.vacant.is_empty());
+    assert_eq!(wheel.timers.len(), n);
+    assert!(consistent(&wheel));
+
+    // The handles of the old timers are stale, whether their nodes have been
+    // reused or freed
+    for &old in &old {
+        assert_eq!(wheel.deadline(old), None);
+        assert_eq!(wheel.cancel(old), None);
+    }
+
+    assert_eq!(wheel.len(), n);
+    assert_eq!(wheel.cancel(new[n - 1]), Some(n as u64 - 1));
+    assert_eq!(wheel.advance(2 * n as u64).len(), n - 1);
+}
@@ -0,0 +1,225 @@
+//! A queue of tasks waiting for a notification, built on a tail list.
+//!
+//! Every pending `Wait` future owns a node of the queue's `TailList`, which
+//! stores the task's `Waker`. Since a node can be removed from its list in
+//! O(1), a future which is dropped before it has been notified (i.e. which has
+//! been cancelled) simply removes its node again.
+//!
+//! Waiters are notified in the order in which they started waiting, that is,
+//! in which their `Wait` futures have first been polled. A `WaitQueue` is not
+//! thread-safe, so it is meant to be used with a local (single threaded)
+//! executor.
+
+use alloc::vec::Vec;
+use allocator_api2::alloc::Global;
+use core::cell::RefCell;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use super::{free_node, val_ptr, NodeRef, Queue};
+
+////////////////////////////////////////////////////////////////////////////////
+// STRUCTS
+////////////////////////////////////////////////////////////////////////////////
+
+/// A queue of waiting tasks (see the module documentation).
+pub struct WaitQueue {
+    state: RefCell<State>,
+}
+
+/// The mutable state of a `WaitQueue`. The oldest waiter is the first one.
+struct State {
+    waiters: Queue<Waiter>,
+}
+
+/// The element of a `WaitQueue`'s list for a single `Wait` future. Once it has
+/// been notified, the node is detached from the list and only owned by the
+/// future. `forward` is whether the notification came from `notify_one`, so it
+/// must be passed on if the future is cancelled.
+struct Waiter {
+    waker: Option<Waker>,
+    notified: bool,
+    forward: bool,
+}
+
+/// A future which completes once it has been notified by its `WaitQueue`, see
+/// `WaitQueue::wait`.
+///
+/// The future starts waiting when it is first polled. Dropping it removes it
+/// from the queue, and if it has already been notified by `notify_one` but not
+/// yet completed, the notification is passed on to the next waiter.
+pub struct Wait<'node> {
+    queue: &'node WaitQueue,
+    node: Option<NodeRef<Waiter, Global>>,
+    done: bool,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// IMPLS
+////////////////////////////////////////////////////////////////////////////////
+
+impl WaitQueue {
+    /// Returns a new, empty queue.
+    pub fn new() -> WaitQueue {
+        WaitQueue {
+            state: RefCell::new(State { waiters: Queue::new() }),
+        }
+    }
+
+    /// Returns a future which completes once it has been notified.
+    pub fn wait<'node>(&'node self) -> Wait<'node> {
+        Wait {
+            queue: self,
+            node: None,
+            done: false,
+        }
+    }
+
+    /// Notifies the waiter which has been waiting the longest, if there is
+    /// any, and returns whether a waiter has been notified.
+    ///
+    /// Notifications are not stored: if no future is waiting, this does
+    /// nothing.
+    pub fn notify_one(&self) -> bool {
+        let waker = match self.state.borrow_mut().notify(true) {
+            Some(waker) => waker,
+            None => return false,
+        };
+
+        // Wake outside of the borrow, the waker may poll the future directly
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+
+        true
+    }
+
+    /// Notifies all waiters and returns their number.
+    pub fn notify_all(&self) -> usize {
+        let mut wakers = Vec::new();
+
+        {
+            let mut state = self.state.borrow_mut();
+
+            while let Some(waker) = state.notify(false) {
+                wakers.push(waker);
+            }
+        }
+
+        let count = wakers.len();
+
+        for waker in wakers.into_iter().flatten() {
+            waker.wake();
+        }
+
+        count
+    }
+}
+
+impl State {
+    /// Notifies and detaches the first waiter, if any, and returns its waker.
+    /// `forward` is whether the notification is passed on if the waiter is
+    /// cancelled.
+    fn notify(&mut self, forward: bool) -> Option<Option<Waker>> {
+        let node_ref = self.waiters.front()?;
+        self.waiters.unlink(&node_ref);
+
+        let waiter = unsafe { &mut *val_ptr(&node_ref) };
+        waiter.notified = true;
+        waiter.forward = forward;
+        Some(waiter.waker.take())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// TRAIT IMPLS
+////////////////////////////////////////////////////////////////////////////////
+
+impl Default for WaitQueue {
+    fn default() -> WaitQueue {
+        WaitQueue::new()
+    }
+}
+
+impl<'node> Future for Wait<'node> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let this = self.get_mut();
+        assert!(!this.done, "`Wait` polled after completion");
+
+        let node_ref = match this.node {
+            Some(ref node_ref) => node_ref.clone(),
+            None => {
+                let waiter = Waiter {
+                    waker: Some(cx.waker().clone()),
+                    notified: false,
+                    forward: false,
+                };
+                this.node = Some(this.queue.state.borrow_mut().waiters.push_back(waiter));
+
+                return Poll::Pending;
+            }
+        };
+
+        // Wakers are only dropped after the borrow has ended, since dropping a
+        // waker may use this queue, e.g. if its task owns another waiter
+        let state = this.queue.state.borrow_mut();
+        let waiter = unsafe { &mut *val_ptr(&node_ref) };
+
+        if waiter.notified {
+            // The node has already been detached by the notification
+            this.node = None;
+            this.done = true;
+            let waiter = unsafe { free_node(node_ref) };
+            drop(state);
+            drop(waiter);
+
+            return Poll::Ready(());
+        }
+
+        let old_waker = match waiter.waker {
+            Some(ref waker) if waker.will_wake(cx.waker()) => None,
+            _ => waiter.waker.replace(cx.waker().clone()),
+        };
+
+        drop(state);
+        drop(old_waker);
+        Poll::Pending
+    }
+}
+
+impl<'node> Drop for Wait<'node> {
+    fn drop(&mut self) {
+        let node_ref = match self.node.take() {
+            Some(node_ref) => node_ref,
+            None => return,
+        };
+
+        let Waiter { waker, forward, .. } = {
+            let mut state = self.queue.state.borrow_mut();
+
+            if unsafe { (*val_ptr(&node_ref)).notified } {
+                // The node has already been detached by the notification
+                unsafe { free_node(node_ref) }
+            } else {
+                state.waiters.remove(node_ref)
+            }
+        };
+
+        // The waker may use this queue when it is dropped, so it is dropped
+        // after the borrow has ended
+        drop(waker);
+
+        // Do not lose the notification of a cancelled waiter. Waiters notified
+        // by `notify_all` have all been woken already.
+        if forward {
+            self.queue.notify_one();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    include!( "./wait_queue_tests.rs");
+}
@@ -0,0 +1,267 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::ptr;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, RawWaker, RawWakerVTable, Wake, Waker};
+use super::{Wait, WaitQueue};
+
+/// Wakes a task of an `Executor` by queueing its index.
+struct TaskWaker {
+    index: usize,
+    woken: Arc<Mutex<Vec<usize>>>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<TaskWaker>) {
+        self.woken.lock().unwrap().push(self.index);
+    }
+}
+
+/// A simple local executor, which polls its tasks when they are woken.
+struct Executor<'node> {
+    tasks: Vec<Option<Pin<Box<dyn Future<Output=()> + 'node>>>>,
+    woken: Arc<Mutex<Vec<usize>>>,
+    completed: Vec<usize>,
+}
+
+impl<'node> Executor<'node> {
+    fn new() -> Executor<'node> {
+        Executor { tasks: Vec::new(), woken: Arc::new(Mutex::new(Vec::new())), completed: Vec::new() }
+    }
+
+    /// Adds a task, which is polled by the next call to `run`.
+    fn spawn<F: Future<Output=()> + 'node>(&mut self, future: F) -> usize {
+        let index = self.tasks.len();
+        self.tasks.push(Some(Box::pin(future)));
+        self.woken.lock().unwrap().push(index);
+        index
+    }
+
+    /// Cancels the given task by dropping it.
+    fn cancel(&mut self, index: usize) {
+        self.tasks[index] = None;
+    }
+
+    /// Polls woken tasks until no task is woken anymore and returns the
+    /// indices of the tasks completed in the meantime, in order.
+    fn run(&mut self) -> Vec<usize> {
+        loop {
+            let woken = std::mem::take(&mut *self.woken.lock().unwrap());
+
+            if woken.is_empty() {
+                return std::mem::take(&mut self.completed);
+            }
+
+            for index in woken {
+                let waker = Waker::from(Arc::new(TaskWaker { index, woken: self.woken.clone() }));
+                let mut cx = Context::from_waker(&waker);
+
+                let ready = match self.tasks[index] {
+                    Some(ref mut task) => task.as_mut().poll(&mut cx).is_ready(),
+                    None => false,
+                };
+
+                if ready {
+                    self.tasks[index] = None;
+                    self.completed.push(index);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn notify_one() {
+    let queue = WaitQueue::new();
+    let mut executor = Executor::new();
+
+    assert!(!queue.notify_one());
+
+    for _ in 0..4 {
+        executor.spawn(queue.wait());
+    }
+    assert_eq!(executor.run(), []);
+
+    // Waiters are notified in order
+    assert!(queue.notify_one());
+    assert_eq!(executor.run(), [0]);
+    assert!(queue.notify_one());
+    assert!(queue.notify_one());
+    assert_eq!(executor.run(), [1, 2]);
+    assert!(queue.notify_one());
+    assert_eq!(executor.run(), [3]);
+
+    // Notifications are not stored
+    assert!(!queue.notify_one());
+    executor.spawn(queue.wait());
+    assert_eq!(executor.run(), []);
+}
+
+#[test]
+fn notify_all() {
+    let queue = WaitQueue::new();
+    let mut executor = Executor::new();
+
+    assert_eq!(queue.notify_all(), 0);
+
+    for _ in 0..4 {
+        executor.spawn(queue.wait());
+    }
+    assert_eq!(executor.run(), []);
+
+    assert_eq!(queue.notify_all(), 4);
+    assert_eq!(executor.run(), [0, 1, 2, 3]);
+    assert_eq!(queue.notify_all(), 0);
+}
+
+#[test]
+fn cancel() {
+    let queue = WaitQueue::new();
+    let mut executor = Executor::new();
+
+    for _ in 0..4 {
+        executor.spawn(queue.wait());
+    }
+    assert_eq!(executor.run(), []);
+
+    // Cancelled waiters are removed from the queue
+    executor.cancel(0);
+    executor.cancel(2);
+    assert!(queue.notify_one());
+    assert_eq!(executor.run(), [1]);
+
+    // The notification of a cancelled waiter is passed on
+    executor.spawn(queue.wait());
+    assert_eq!(executor.run(), []);
+    assert!(queue.notify_one());
+    executor.cancel(3);
+    assert_eq!(executor.run(), [4]);
+
+    // A future which has not been polled is not waiting yet
+    drop(queue.wait());
+    assert!(!queue.notify_one());
+}
+
+#[test]
+fn cancel_after_notify_all() {
+    let queue = WaitQueue::new();
+    let mut executor = Executor::new();
+
+    for _ in 0..2 {
+        executor.spawn(queue.wait());
+    }
+    assert_eq!(executor.run(), []);
+    assert_eq!(queue.notify_all(), 2);
+
+    // A waiter which starts waiting later
+    let mut late = Executor::new();
+    late.spawn(queue.wait());
+    assert_eq!(late.run(), []);
+
+    // The notification of a waiter cancelled after `notify_all` is not passed
+    // on, since it was meant for the earlier waiters only
+    executor.cancel(0);
+    assert_eq!(executor.run(), [1]);
+    assert_eq!(late.run(), []);
+
+    assert!(queue.notify_one());
+    assert_eq!(late.run(), [0]);
+}
+
+#[test]
+fn update_waker() {
+    let queue = WaitQueue::new();
+    let mut executor = Executor::new();
+    let mut wait = Box::pin(queue.wait());
+
+    // First polled by a different task
+    {
+        let waker = Waker::from(Arc::new(TaskWaker { index: 0, woken: Arc::new(Mutex::new(Vec::new())) }));
+        assert!(wait.as_mut().poll(&mut Context::from_waker(&waker)).is_pending());
+    }
+
+    // Polling the future again replaces the waker
+    let index = executor.spawn(wait);
+    assert_eq!(executor.run(), []);
+    assert!(queue.notify_one());
+    assert_eq!(executor.run(), [index]);
+}
+
+#[test]
+fn moved_queue() {
+    let mut queues = vec![WaitQueue::new()];
+
+    // Push nodes and move the queue
+    {
+        let mut executor = Executor::new();
+        executor.spawn(queues[0].wait());
+        assert_eq!(executor.run(), []);
+        assert_eq!(queues[0].notify_all(), 1);
+    }
+    queues.reserve(64);
+
+    let mut executor = Executor::new();
+    executor.spawn(queues[0].wait());
+    executor.spawn(queues[0].wait());
+    assert_eq!(executor.run(), []);
+    assert!(queues[0].notify_one());
+    assert_eq!(executor.run(), [0]);
+}
+
+#[test]
+#[should_panic(expected = "polled after completion")]
+fn poll_after_completion() {
+    let queue = WaitQueue::new();
+    let mut wait: Wait = queue.wait();
+    let waker = Waker::from(Arc::new(TaskWaker { index: 0, woken: Arc::new(Mutex::new(Vec::new())) }));
+    let mut cx = Context::from_waker(&waker);
+
+    assert!(Pin::new(&mut wait).poll(&mut cx).is_pending());
+    assert!(queue.notify_one());
+    assert!(Pin::new(&mut wait).poll(&mut cx).is_ready());
+    let _ = Pin::new(&mut wait).poll(&mut cx);
+}
+
+thread_local! {
+    /// The queue used by `notifying_waker`.
+    static QUEUE: WaitQueue = WaitQueue::new();
+}
+
+/// Returns a waker which notifies a waiter of `QUEUE` when it is dropped, like
+/// the waker of a task which owns another waiter of the queue.
+fn notifying_waker() -> Waker {
+    fn clone(_: *const ()) -> RawWaker {
+        RawWaker::new(ptr::null(), &VTABLE)
+    }
+
+    fn wake(_: *const ()) {}
+
+    fn drop(_: *const ()) {
+        QUEUE.with(|queue| queue.notify_one());
+    }
+
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake, drop);
+
+    unsafe { Waker::from_raw(RawWaker::new(ptr::null(), &VTABLE)) }
+}
+
+#[test]
+fn waker_drop_uses_queue() {
+    QUEUE.with(|queue| {
+        let waker = notifying_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        // The waker is replaced by polling with a different one
+        let mut wait = Box::pin(queue.wait());
+        assert!(wait.as_mut().poll(&mut cx).is_pending());
+        assert!(wait.as_mut().poll(&mut Context::from_waker(Waker::noop())).is_pending());
+
+        // The waker is dropped by cancelling the waiter
+        let mut cancelled = Box::pin(queue.wait());
+        assert!(cancelled.as_mut().poll(&mut cx).is_pending());
+        drop(cancelled);
+
+        // The dropped wakers have notified the first waiter, which completes
+        assert!(wait.as_mut().poll(&mut cx).is_ready());
+    });
+}